@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the vendored protoc so that building does not need one installed.
+    if std::env::var_os("PROTOC").is_none() {
+        // SAFETY: the build script is single threaded.
+        unsafe {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        }
+    }
+    tonic_prost_build::compile_protos("proto/act.proto")?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#ifndef ACT_H
+#define ACT_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#define ACT_DEPOSIT 0
+
+#define ACT_WITHDRAWAL 1
+
+#define ACT_DISPUTE 2
+
+#define ACT_RESOLVE 3
+
+#define ACT_CHARGEBACK 4
+
+/*
+ Result of a call, `act_last_error` describes anything but `Ok`.
+ */
+typedef enum ActStatus {
+  ACT_STATUS_OK = 0,
+  /*
+   The transaction was valid but not applied, e.g. insufficient funds
+   */
+  ACT_STATUS_REJECTED = 1,
+  /*
+   Null pointer, unknown transaction type or unparsable CSV line
+   */
+  ACT_STATUS_INVALID = 2,
+  ACT_STATUS_NOT_FOUND = 3,
+  ACT_STATUS_ERROR = 4,
+} ActStatus;
+
+/*
+ Snapshot of the accounts of an engine, ordered by client id.
+ */
+typedef struct ActAccountIter ActAccountIter;
+
+/*
+ Opaque engine handle.
+ */
+typedef struct ActEngine ActEngine;
+
+typedef struct ActAccount {
+  uint16_t client;
+  int64_t available;
+  uint64_t held;
+  int64_t total;
+  bool locked;
+} ActAccount;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Engine with in-memory stores, free with `act_engine_free`.
+ */
+struct ActEngine *act_engine_new(void);
+
+/*
+ Engine keeping accounts and transactions in a SQLite database, null if
+ it cannot be opened.
+
+ # Safety
+
+ `path` must be null or a NUL-terminated string.
+ */
+struct ActEngine *act_engine_open_sqlite(const char *path);
+
+/*
+ # Safety
+
+ `engine` must be null or returned by `act_engine_new` or
+ `act_engine_open_sqlite` and not freed yet.
+ */
+void act_engine_free(struct ActEngine *engine);
+
+/*
+ Applies a transaction, `amount` is ignored for disputes, resolves and
+ chargebacks.
+
+ # Safety
+
+ `engine` must be null or a live engine.
+ */
+enum ActStatus act_apply(struct ActEngine *engine,
+                         uint32_t tx_type,
+                         uint16_t client,
+                         uint32_t tx,
+                         uint64_t amount);
+
+/*
+ Applies a CSV line with the columns `type,client,tx,amount`, parsed with
+ the rules of input files.
+
+ # Safety
+
+ `engine` must be null or a live engine, `line` null or a NUL-terminated
+ string.
+ */
+enum ActStatus act_apply_csv(struct ActEngine *engine, const char *line);
+
+/*
+ Message of the last call that did not return `ACT_STATUS_OK`, null if
+ the last call succeeded. Valid until the next call on the engine.
+
+ # Safety
+
+ `engine` must be null or a live engine.
+ */
+const char *act_last_error(const struct ActEngine *engine);
+
+/*
+ Writes the account of `client` to `out`.
+
+ # Safety
+
+ `engine` must be null or a live engine, `out` null or writable.
+ */
+enum ActStatus act_get_account(struct ActEngine *engine, uint16_t client, struct ActAccount *out);
+
+/*
+ Snapshot of every account, unaffected by later transactions. Free with
+ `act_accounts_free`.
+
+ # Safety
+
+ `engine` must be null or a live engine.
+ */
+struct ActAccountIter *act_accounts(const struct ActEngine *engine);
+
+/*
+ Writes the next account to `out`, false once every account was read.
+
+ # Safety
+
+ `iter` must be null or returned by `act_accounts` and not freed yet,
+ `out` null or writable.
+ */
+bool act_accounts_next(struct ActAccountIter *iter, struct ActAccount *out);
+
+/*
+ # Safety
+
+ `iter` must be null or returned by `act_accounts` and not freed yet.
+ */
+void act_accounts_free(struct ActAccountIter *iter);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ACT_H */
@@ -0,0 +1,200 @@
+use crate::output::{OutputFormat, RecordWriter};
+use crate::types::amount::format_amount;
+use crate::types::{Account, Transaction, TransactionType, TxRecord};
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Applied,
+    Rejected,
+}
+
+/// Outcome of one input row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub row: u64,
+    /// Unix seconds of the input row, if it has one
+    pub timestamp: Option<i64>,
+    /// None if the row could not be parsed
+    pub tx: Option<Transaction>,
+    pub outcome: Outcome,
+    /// Why the row was rejected
+    pub reason: Option<String>,
+    /// Account of the client before the row was processed
+    pub before: Option<Account>,
+    /// Account of the client after the row was processed
+    pub after: Option<Account>,
+    /// Transaction referenced by a dispute, resolve or chargeback as it was
+    /// before the row, if it is known
+    pub referenced: Option<TxRecord>,
+}
+
+impl AuditRecord {
+    /// Record of a row that could not be parsed.
+    pub fn invalid(row: u64, reason: String) -> Self {
+        AuditRecord {
+            row,
+            timestamp: None,
+            tx: None,
+            outcome: Outcome::Rejected,
+            reason: Some(reason),
+            before: None,
+            after: None,
+            referenced: None,
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: Option<i64>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn applied(&self) -> bool {
+        self.outcome == Outcome::Applied
+    }
+}
+
+/// Receives the outcome of every processed row.
+pub trait AuditSink {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()>;
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for &mut S {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        (**self).record(rec)
+    }
+}
+
+/// Flat form of an [`AuditRecord`], amounts are exact decimal strings.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct AuditRow {
+    row: u64,
+    #[serde(rename = "type")]
+    tx_type: Option<TransactionType>,
+    client: Option<u16>,
+    tx: Option<u32>,
+    amount: Option<String>,
+    outcome: Outcome,
+    reason: Option<String>,
+    available: Option<String>,
+    held: Option<String>,
+    total: Option<String>,
+    locked: Option<bool>,
+}
+
+impl From<&AuditRecord> for AuditRow {
+    fn from(rec: &AuditRecord) -> Self {
+        let t = rec.tx.as_ref();
+        let act = rec.after.as_ref();
+        AuditRow {
+            row: rec.row,
+            tx_type: t.map(|t| t.tx_type),
+            client: t.map(|t| t.client),
+            tx: t.map(|t| t.tx),
+            // Disputes, resolves and chargebacks have no amount of their own.
+            amount: t
+                .filter(|t| {
+                    matches!(
+                        t.tx_type,
+                        TransactionType::Deposit | TransactionType::Withdrawal
+                    )
+                })
+                .map(|t| format_amount(t.amount)),
+            outcome: rec.outcome,
+            reason: rec.reason.clone(),
+            available: act.map(|a| format_amount(a.available_exact())),
+            held: act.map(|a| format_amount(a.held())),
+            total: act.map(|a| format_amount(a.total())),
+            locked: act.map(|a| a.is_locked()),
+        }
+    }
+}
+
+/// Writes audit records as CSV, a JSON array or JSON lines.
+pub struct AuditWriter<W: Write>(RecordWriter<W>);
+
+impl<W: Write> AuditWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        AuditWriter(RecordWriter::new(writer, format))
+    }
+
+    pub fn finish(self) -> Result<W> {
+        self.0.finish()
+    }
+}
+
+impl<W: Write> AuditSink for AuditWriter<W> {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        self.0.write(&AuditRow::from(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn records() -> Vec<AuditRecord> {
+        let mut records = fixtures::records(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Dispute, 1, 1, 0),
+            (TransactionType::Withdrawal, 1, 1, 5000),
+        ]);
+        records.push(AuditRecord::invalid(4, String::from("bad row")));
+        records
+    }
+
+    #[test]
+    fn test_records() {
+        let records = records();
+        assert!(records[0].applied());
+        assert_eq!(None, records[0].before);
+        assert_eq!(10000, records[0].after.as_ref().unwrap().total());
+        assert!(records[1].applied());
+        assert_eq!(10000, records[1].after.as_ref().unwrap().held());
+        assert_eq!(Outcome::Rejected, records[2].outcome);
+        assert!(records[2].reason.is_some());
+        assert_eq!(records[2].before, records[2].after);
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut writer = AuditWriter::new(Vec::new(), OutputFormat::Csv);
+        for rec in records() {
+            writer.record(&rec).unwrap();
+        }
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            "row,type,client,tx,amount,outcome,reason,available,held,total,locked",
+            lines[0]
+        );
+        assert_eq!(
+            "1,deposit,1,1,1.0000,applied,,1.0000,0.0000,1.0000,false",
+            lines[1]
+        );
+        assert_eq!(
+            "2,dispute,1,1,,applied,,0.0000,1.0000,1.0000,false",
+            lines[2]
+        );
+        assert!(lines[3].starts_with("3,withdrawal,1,1,0.5000,rejected,"));
+        assert!(lines[3].ends_with(",0.0000,1.0000,1.0000,false"));
+        assert_eq!("4,,,,,rejected,bad row,,,,", lines[4]);
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let mut writer = AuditWriter::new(Vec::new(), OutputFormat::Jsonl);
+        writer.record(&records()[0]).unwrap();
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            "{\"row\":1,\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.0000\",\
+             \"outcome\":\"applied\",\"reason\":null,\"available\":\"1.0000\",\
+             \"held\":\"0.0000\",\"total\":\"1.0000\",\"locked\":false}\n",
+            out
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use crate::process::process_in_memory;
+use crate::{input_arg, open_input};
+use act::config::Config;
+use act::explain::Explainer;
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+use std::io::Write;
+
+pub fn command() -> Command {
+    Command::new("explain")
+        .about("Trace the balance history of a single client")
+        .arg(
+            Arg::new("client")
+                .long("client")
+                .required(true)
+                .value_parser(clap::value_parser!(u16))
+                .help("Client to explain"),
+        )
+        .arg(input_arg())
+}
+
+/// Processes the input and prints the ledger of one client.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let client = *matches
+        .get_one::<u16>("client")
+        .expect("client is required");
+    let input = open_input(matches)?;
+    let mut explainer = Explainer::new(client);
+    process_in_memory(input, vec![&mut explainer], config.policy).await?;
+    let mut out = std::io::stdout().lock();
+    explainer.write_text(&mut out)?;
+    out.flush()?;
+    Ok(())
+}
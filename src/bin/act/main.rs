@@ -0,0 +1,162 @@
+mod explain;
+mod process;
+mod reconcile;
+mod serve;
+mod snapshot;
+mod validate;
+mod watch;
+
+use act::config::{ActBackend, Config, StoreConfig, TxBackend};
+use act::output::OutputFormat;
+use anyhow::Result;
+use clap::{Arg, ArgAction::Count, ArgMatches, Command, command, parser::ValueSource};
+use log::LevelFilter;
+use std::fs::File;
+use std::io::{BufRead, BufReader, stdin};
+use std::path::{Path, PathBuf};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = process::args(command!())
+        .args_conflicts_with_subcommands(true)
+        .subcommand(process::args(
+            Command::new("process").about("Process the input and write the final accounts"),
+        ))
+        .subcommand(validate::command())
+        .subcommand(explain::command())
+        .subcommand(reconcile::command())
+        .subcommand(snapshot::command())
+        .subcommand(watch::command())
+        .subcommand(serve::command())
+        .arg(
+            Arg::new("debug")
+                .short('d')
+                .long("debug")
+                .required(false)
+                .global(true)
+                .action(Count)
+                .help("Log more, repeat up to 4 times"),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .global(true)
+                .value_parser(["off", "error", "warn", "info", "debug", "trace"])
+                .help("Log level, overrides -d and RUST_LOG"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .global(true)
+                .help("TOML file of policies, formats and stores, overridden by options"),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .global(true)
+                .value_parser(|s: &str| s.parse::<OutputFormat>().map_err(|e| e.to_string()))
+                .default_value("csv")
+                .help("Output format: csv, json or jsonl"),
+        )
+        .get_matches();
+
+    init_logger(&matches);
+    // `act <file>` is `act process <file>`.
+    let (name, matches) = matches.subcommand().unwrap_or(("process", &matches));
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => Config::load(Path::new(path))?,
+        None => Config::default(),
+    };
+    match name {
+        "validate" => validate::run(matches, &config).await,
+        "explain" => explain::run(matches, &config).await,
+        "reconcile" => reconcile::run(matches, &config).await,
+        "snapshot" => snapshot::run(matches, &config).await,
+        "watch" => watch::run(matches, &config).await,
+        "serve" => serve::run(matches, &config).await,
+        _ => process::run(matches, &config).await,
+    }
+}
+
+fn init_logger(matches: &ArgMatches) {
+    let level = match matches.get_one::<String>("log-level") {
+        Some(level) => level.parse().ok(),
+        None => match matches.get_count("debug") {
+            1 => Some(LevelFilter::Warn),
+            2 => Some(LevelFilter::Info),
+            3 => Some(LevelFilter::Debug),
+            4 => Some(LevelFilter::Trace),
+            _ => None,
+        },
+    };
+    let mut logger = env_logger::Builder::new();
+    logger.parse_env(env_logger::DEFAULT_FILTER_ENV);
+    if let Some(l) = level {
+        logger.filter_level(l);
+    };
+    logger.init();
+}
+
+/// Positional input file argument of the commands reading transactions.
+fn input_arg() -> Arg {
+    Arg::new("input")
+        .required(false)
+        .index(1)
+        .help("Input file, stdin if omitted or -")
+}
+
+/// Input file of the command, stdin if omitted or -.
+fn open_input(matches: &ArgMatches) -> Result<Box<dyn BufRead>> {
+    let input_arg = matches
+        .get_one::<String>("input")
+        .map(|s| s.as_str())
+        .unwrap_or("-");
+    Ok(match input_arg {
+        "-" | "" => Box::new(BufReader::new(stdin())),
+        f => Box::new(BufReader::new(File::open(f)?)),
+    })
+}
+
+/// Value of an option given on the command line, None if left to its
+/// default so that the configuration applies.
+fn explicit<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Option<T> {
+    if !matches.ids().any(|i| i == id) || matches.value_source(id) != Some(ValueSource::CommandLine)
+    {
+        return None;
+    }
+    matches.get_one::<T>(id).cloned()
+}
+
+/// Output format of the option, or of the configuration.
+fn output_format(matches: &ArgMatches, config: &Config) -> OutputFormat {
+    explicit(matches, "output-format").unwrap_or(config.output.format)
+}
+
+/// Stores of the configuration with the store options of the command
+/// applied.
+fn store_config(matches: &ArgMatches, config: &Config) -> Result<StoreConfig> {
+    let mut store = config.store.clone();
+    if let Some(backend) = explicit::<String>(matches, "store") {
+        store.accounts = match backend.as_str() {
+            "sqlite" => ActBackend::Sqlite,
+            _ => ActBackend::Mem,
+        };
+    }
+    if let Some(db) = explicit::<String>(matches, "db") {
+        store.db = PathBuf::from(db);
+    }
+    if let Some(backend) = explicit::<String>(matches, "tx-store") {
+        store.transactions = match backend.as_str() {
+            "spill" => TxBackend::Spill,
+            _ => TxBackend::Mem,
+        };
+    }
+    if let Some(budget) = explicit(matches, "tx-mem-budget") {
+        store.tx_mem_budget = budget;
+    }
+    if let Some(dir) = explicit::<String>(matches, "tx-spill-dir") {
+        store.spill_dir = Some(PathBuf::from(dir));
+    }
+    store.check()?;
+    Ok(store)
+}
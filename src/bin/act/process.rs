@@ -0,0 +1,585 @@
+use crate::{explicit, input_arg, open_input, output_format, store_config};
+use act::audit::{AuditSink, AuditWriter};
+use act::config::{ActBackend, Config, TxBackend};
+use act::engine::Engine;
+use act::follow::Follower;
+use act::history::{Cutoff, until};
+use act::invariants::InvariantChecker;
+use act::journal::{JournalWriter, TrialBalance};
+use act::output::{OutputFormat, RecordWriter};
+use act::parallel::ShardedProcessor;
+use act::parse::{Row, parse_rows, transactions};
+use act::process::Policy;
+use act::stores::{
+    AccountFilter, AccountOrder, ActStore, MemActStore, MemTxStore, SpillTxStore, SqliteActStore,
+    TxStore,
+};
+use act::summary::Summary;
+use act::types::account::AccountSer;
+use act::types::amount::parse_signed_amount;
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction::SetTrue, ArgMatches, Command, parser::ValueSource};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio_stream::{Stream, StreamExt};
+
+/// How often a followed file is checked for new rows.
+const FOLLOW_POLL: Duration = Duration::from_millis(250);
+
+/// Options of `act process`, also taken by `act` itself.
+pub fn args(cmd: Command) -> Command {
+    cmd.arg(input_arg())
+        .arg(
+            Arg::new("store")
+                .long("store")
+                .value_parser(["mem", "sqlite"])
+                .default_value("mem")
+                .help("Account store backend"),
+        )
+        .arg(
+            Arg::new("db")
+                .long("db")
+                .default_value("act.db")
+                .help("SQLite database file, used with --store sqlite"),
+        )
+        .arg(
+            Arg::new("tx-store")
+                .long("tx-store")
+                .value_parser(["mem", "spill"])
+                .default_value("mem")
+                .help(
+                    "Transaction store, spill keeps a bounded set in memory and the rest on disk",
+                ),
+        )
+        .arg(
+            Arg::new("tx-mem-budget")
+                .long("tx-mem-budget")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .default_value("256")
+                .help("Memory budget of the spill transaction store in MiB"),
+        )
+        .arg(
+            Arg::new("tx-spill-dir")
+                .long("tx-spill-dir")
+                .help("Directory for spilled transactions, defaults to a temporary directory"),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .default_value("1")
+                .help("Number of threads, clients are split between them"),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .value_parser(|s: &str| s.parse::<Cutoff>().map_err(|e| e.to_string()))
+                .help(
+                    "Stop after row:<n>, tx:<id> or before the first row timestamped after \
+                     time:<timestamp>, unix seconds or RFC 3339",
+                ),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .value_parser(["client", "balance", "locked"])
+                .default_value("client")
+                .help("Output order, balance puts the largest totals first"),
+        )
+        .arg(
+            Arg::new("only-locked")
+                .long("only-locked")
+                .action(SetTrue)
+                .help("Only output locked accounts"),
+        )
+        .arg(
+            Arg::new("min-total")
+                .long("min-total")
+                .allow_negative_numbers(true)
+                .value_parser(|s: &str| parse_signed_amount(s).map_err(|e| e.to_string()))
+                .help("Only output accounts with at least this total"),
+        )
+        .arg(
+            Arg::new("client")
+                .long("client")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u16))
+                .help("Only output these clients, e.g. 1,2,5"),
+        )
+        .arg(
+            Arg::new("disputes")
+                .long("disputes")
+                .action(SetTrue)
+                .help("Add the number of open disputes of each account"),
+        )
+        .arg(
+            Arg::new("audit")
+                .long("audit")
+                .help("Write the outcome of every input row to this file"),
+        )
+        .arg(
+            Arg::new("audit-format")
+                .long("audit-format")
+                .value_parser(|s: &str| s.parse::<OutputFormat>().map_err(|e| e.to_string()))
+                .default_value("csv")
+                .help("Audit format: csv, json or jsonl"),
+        )
+        .arg(
+            Arg::new("journal")
+                .long("journal")
+                .help("Write the double-entry postings of every row to this file"),
+        )
+        .arg(
+            Arg::new("journal-format")
+                .long("journal-format")
+                .value_parser(|s: &str| s.parse::<OutputFormat>().map_err(|e| e.to_string()))
+                .default_value("csv")
+                .help("Journal format: csv, json or jsonl"),
+        )
+        .arg(
+            Arg::new("trial-balance")
+                .long("trial-balance")
+                .action(SetTrue)
+                .help("Print the trial balance of the ledger to stderr"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(SetTrue)
+                .help("Check the accounts against the accepted rows once processing is done"),
+        )
+        .arg(
+            Arg::new("check-every")
+                .long("check-every")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Also check the accounts every N rows"),
+        )
+        .arg(
+            Arg::new("summary")
+                .long("summary")
+                .value_parser(["text", "json"])
+                .help("Print run statistics to stderr"),
+        )
+        .arg(
+            Arg::new("follow")
+                .long("follow")
+                .action(SetTrue)
+                .help("Keep reading rows appended to the input file until interrupted"),
+        )
+        .arg(
+            Arg::new("snapshot-interval")
+                .long("snapshot-interval")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("10")
+                .help("Seconds between account outputs with --follow"),
+        )
+}
+
+/// Processes the input and writes the final accounts.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let store = store_config(matches, config)?;
+    let tx_opts = TxStoreOpts {
+        spill: store.transactions == TxBackend::Spill,
+        budget: store.tx_mem_budget_bytes()?,
+        dir: store.spill_dir.clone(),
+    };
+    let jobs = *matches
+        .get_one::<usize>("jobs")
+        .expect("jobs has a default");
+    let cutoff = matches.get_one::<Cutoff>("until").copied();
+
+    let order = match explicit::<String>(matches, "sort").as_deref() {
+        Some("balance") => AccountOrder::Balance,
+        Some("locked") => AccountOrder::Locked,
+        Some(_) => AccountOrder::Client,
+        None => config.output.sort,
+    };
+    let filter = AccountFilter {
+        locked: matches.get_flag("only-locked").then_some(true),
+        min_total: matches.get_one::<i64>("min-total").copied(),
+        clients: matches
+            .get_many::<u16>("client")
+            .map(|clients| clients.copied().collect()),
+        ..Default::default()
+    };
+    let format = output_format(matches, config);
+    if matches.get_flag("follow") {
+        return follow(matches, config, &filter, order, format).await;
+    }
+
+    let reached = Arc::new(AtomicBool::new(false));
+    let rows = until(
+        parse_rows(open_input(matches)?),
+        cutoff.unwrap_or(Cutoff::Row(u64::MAX)),
+        reached.clone(),
+    );
+    let mut audit = match matches.get_one::<String>("audit") {
+        Some(_) if jobs > 1 => bail!("--audit is not supported with --jobs"),
+        Some(path) => {
+            let format = explicit(matches, "audit-format").unwrap_or(config.output.audit_format);
+            Some(AuditWriter::new(
+                BufWriter::new(File::create(path)?),
+                format,
+            ))
+        }
+        None => None,
+    };
+    let mut journal = match matches.get_one::<String>("journal") {
+        Some(_) if jobs > 1 => bail!("--journal is not supported with --jobs"),
+        Some(path) => {
+            let format =
+                explicit(matches, "journal-format").unwrap_or(config.output.journal_format);
+            Some(JournalWriter::new(
+                BufWriter::new(File::create(path)?),
+                format,
+            ))
+        }
+        None => None,
+    };
+    let mut trial = match matches.get_flag("trial-balance") {
+        true if jobs > 1 => bail!("--trial-balance is not supported with --jobs"),
+        true => Some(TrialBalance::new()),
+        false => None,
+    };
+    let every = matches.get_one::<u64>("check-every").copied();
+    let mut checks = match matches.get_flag("check") || every.is_some() {
+        true if jobs > 1 => bail!("--check is not supported with --jobs"),
+        true => Some(Checks {
+            checker: InvariantChecker::new(),
+            every,
+            violations: 0,
+        }),
+        false => None,
+    };
+    let mut summary = match matches.get_one::<String>("summary") {
+        Some(_) if jobs > 1 => bail!("--summary is not supported with --jobs"),
+        Some(_) => Some(Summary::new()),
+        None => None,
+    };
+    let mut sinks: Vec<&mut (dyn AuditSink + Send)> = Vec::new();
+    if let Some(summary) = summary.as_mut() {
+        sinks.push(summary);
+    }
+    if let Some(audit) = audit.as_mut() {
+        sinks.push(audit);
+    }
+    if let Some(journal) = journal.as_mut() {
+        sinks.push(journal);
+    }
+    if let Some(trial) = trial.as_mut() {
+        sinks.push(trial);
+    }
+
+    let (act_store, tx_stores) = match store.accounts {
+        ActBackend::Sqlite => {
+            apply_input(
+                jobs,
+                |_| {
+                    let act_store = SqliteActStore::open(&store.db)?;
+                    let tx_store: Box<dyn TxStore + Send> = Box::new(act_store.tx_store());
+                    Ok((act_store, tx_store))
+                },
+                config.policy,
+                rows,
+                sinks,
+                checks.as_mut(),
+            )
+            .await?
+        }
+        ActBackend::Mem => {
+            apply_input(
+                jobs,
+                |_| Ok((MemActStore::new(), tx_opts.open(jobs)?)),
+                config.policy,
+                rows,
+                sinks,
+                checks.as_mut(),
+            )
+            .await?
+        }
+    };
+    if let Some(audit) = audit {
+        audit.finish()?.flush()?;
+    }
+    if let Some(journal) = journal {
+        journal.finish()?.flush()?;
+    }
+    if let Some(Cutoff::Tx(id)) = cutoff
+        && !reached.load(Ordering::Relaxed)
+    {
+        bail!("--until tx:{}: transaction not found in the input", id);
+    }
+    if let Some(summary) = summary {
+        let report = summary.report(act_store.as_ref());
+        let mut err = std::io::stderr().lock();
+        match matches.get_one::<String>("summary").map(|s| s.as_str()) {
+            Some("json") => {
+                serde_json::to_writer_pretty(&mut err, &report)?;
+                writeln!(err)?;
+            }
+            _ => report.write_text(&mut err)?,
+        }
+    }
+    if let Some(mut checks) = checks {
+        checks.check("processing", act_store.as_ref());
+        if checks.violations > 0 {
+            bail!("{} invariant violations", checks.violations);
+        }
+    }
+    if let Some(trial) = trial {
+        trial.write_text(std::io::stderr().lock())?;
+        if !trial.is_balanced() {
+            bail!("Trial balance does not net to zero");
+        }
+        for mismatch in trial.mismatches() {
+            eprintln!("Ledger disagrees with the accounts after {}", mismatch);
+        }
+        if !trial.mismatches().is_empty() {
+            bail!("{} ledger mismatches", trial.mismatches().len());
+        }
+    }
+
+    let disputes = match matches.get_flag("disputes") {
+        true => {
+            let mut counts = BTreeMap::new();
+            for tx_store in tx_stores.iter() {
+                for (client, count) in tx_store.dispute_counts()? {
+                    *counts.entry(client).or_default() += count;
+                }
+            }
+            Some(counts)
+        }
+        false => None,
+    };
+
+    write_accounts(
+        act_store.as_ref(),
+        &filter,
+        order,
+        format,
+        disputes.as_ref(),
+    )
+}
+
+/// Writes the selected accounts to stdout.
+fn write_accounts(
+    act_store: &dyn ActStore,
+    filter: &AccountFilter,
+    order: AccountOrder,
+    format: OutputFormat,
+    disputes: Option<&BTreeMap<u16, usize>>,
+) -> Result<()> {
+    let mut writer = RecordWriter::new(std::io::stdout().lock(), format);
+    for act in act_store.select(filter, order) {
+        let row = AccountSer::from(act);
+        match disputes {
+            Some(counts) => writer
+                .write(&row.with_disputes(counts.get(&act.id()).copied().unwrap_or_default()))?,
+            None => writer.write(&row)?,
+        }
+    }
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+/// Processes the input file then the rows appended to it until interrupted,
+/// writing the accounts after catching up, then at most every snapshot
+/// interval while rows are applied and once more when interrupted.
+async fn follow(
+    matches: &ArgMatches,
+    config: &Config,
+    filter: &AccountFilter,
+    order: AccountOrder,
+    format: OutputFormat,
+) -> Result<()> {
+    let unsupported = [
+        "jobs",
+        "until",
+        "audit",
+        "journal",
+        "trial-balance",
+        "check",
+        "check-every",
+        "summary",
+    ];
+    for arg in unsupported {
+        if matches.value_source(arg) == Some(ValueSource::CommandLine) {
+            bail!("--{} is not supported with --follow", arg);
+        }
+    }
+    let path = match matches.get_one::<String>("input").map(|s| s.as_str()) {
+        None | Some("-") | Some("") => bail!("--follow needs an input file"),
+        Some(path) => path,
+    };
+    let every = Duration::from_secs(
+        *matches
+            .get_one::<u64>("snapshot-interval")
+            .expect("snapshot interval has a default"),
+    );
+    let store = store_config(matches, config)?;
+    let mut engine = Engine::builder()
+        .stores(store.stores()?)
+        .policy(config.policy)
+        .build();
+    let mut follower = Follower::open(Path::new(path))?;
+
+    let emit = |engine: &Engine| -> Result<()> {
+        let disputes = match matches.get_flag("disputes") {
+            true => Some(engine.tx_store().dispute_counts()?),
+            false => None,
+        };
+        write_accounts(engine.act_store(), filter, order, format, disputes.as_ref())
+    };
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let mut emitted: Option<Instant> = None;
+    let mut changed = true;
+    loop {
+        for row in follower.poll()? {
+            let rec = engine.apply_row(row)?;
+            if rec.tx.is_none() {
+                eprintln!(
+                    "Error reading CSV: {}",
+                    rec.reason.as_deref().unwrap_or_default()
+                );
+            }
+            changed = true;
+        }
+        if follower.behind() {
+            continue;
+        }
+        if changed && emitted.is_none_or(|at| at.elapsed() >= every) {
+            emit(&engine)?;
+            emitted = Some(Instant::now());
+            changed = false;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(FOLLOW_POLL) => {}
+            res = &mut interrupted => {
+                res?;
+                break;
+            }
+        }
+    }
+    if changed {
+        emit(&engine)?;
+    }
+    Ok(())
+}
+
+/// Processes the input sequentially with in-memory stores.
+pub async fn process_in_memory(
+    input: Box<dyn BufRead>,
+    sinks: Vec<&mut (dyn AuditSink + Send)>,
+    policy: Policy,
+) -> Result<Box<dyn ActStore>> {
+    let (act_store, _) = apply_input(
+        1,
+        |_| {
+            let tx_store: Box<dyn TxStore + Send> = Box::new(MemTxStore::new());
+            Ok((MemActStore::new(), tx_store))
+        },
+        policy,
+        parse_rows(input),
+        sinks,
+        None,
+    )
+    .await?;
+    Ok(act_store)
+}
+
+/// Transaction store of the memory account store.
+struct TxStoreOpts {
+    spill: bool,
+    /// Memory budget in bytes, split between shards
+    budget: usize,
+    /// Spill directory, a temporary one if None
+    dir: Option<PathBuf>,
+}
+
+impl TxStoreOpts {
+    fn open(&self, shards: usize) -> Result<Box<dyn TxStore + Send>> {
+        if !self.spill {
+            return Ok(Box::new(MemTxStore::new()));
+        }
+        let budget = self.budget / shards;
+        Ok(Box::new(match &self.dir {
+            Some(dir) => SpillTxStore::new(dir, budget)?,
+            None => SpillTxStore::temporary(budget)?,
+        }))
+    }
+}
+
+/// Invariant checks of the sequential path.
+struct Checks {
+    checker: InvariantChecker,
+    every: Option<u64>,
+    /// Violations reported so far
+    violations: usize,
+}
+
+impl Checks {
+    fn check(&mut self, after: &str, act_store: &dyn ActStore) {
+        for violation in self.checker.check(act_store) {
+            eprintln!("Invariant violated after {}: {}", after, violation);
+            self.violations += 1;
+        }
+    }
+}
+
+/// Applies the input to new stores, sharded if `jobs` is more than one.
+/// `stores` opens the account and transaction stores of a shard.
+async fn apply_input<S, F, R>(
+    jobs: usize,
+    stores: F,
+    policy: Policy,
+    rows: R,
+    sinks: Vec<&mut (dyn AuditSink + Send)>,
+    mut checks: Option<&mut Checks>,
+) -> Result<(Box<dyn ActStore>, Vec<Box<dyn TxStore + Send>>)>
+where
+    S: ActStore + Send + 'static,
+    F: Fn(usize) -> Result<(S, Box<dyn TxStore + Send>)>,
+    R: Stream<Item = Row>,
+{
+    if jobs > 1 {
+        let processor = ShardedProcessor::with_stores(jobs, stores, policy)?;
+        processor.send_all(transactions(rows)).await?;
+        let (act_store, tx_stores) = processor.finish()?;
+        return Ok((Box::new(act_store), tx_stores));
+    }
+
+    let (act_store, tx_store) = stores(0)?;
+    let mut engine = Engine::builder()
+        .act_store(Box::new(act_store))
+        .tx_store(tx_store)
+        .policy(policy);
+    for sink in sinks {
+        engine = engine.sink(sink);
+    }
+    let mut engine = engine.build();
+    tokio::pin!(rows);
+    while let Some(row) = rows.next().await {
+        let rec = engine.apply_row(row)?;
+        if rec.tx.is_none() {
+            eprintln!(
+                "Error reading CSV: {}",
+                rec.reason.as_deref().unwrap_or_default()
+            );
+        }
+        if let Some(checks) = checks.as_mut() {
+            checks.checker.record(&rec)?;
+            if checks.every.is_some_and(|n| rec.row % n == 0) {
+                checks.check(&format!("row {}", rec.row), engine.act_store());
+            }
+        }
+    }
+    let (act_store, tx_store) = engine.into_stores();
+    Ok((act_store, vec![tx_store]))
+}
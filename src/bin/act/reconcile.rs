@@ -0,0 +1,44 @@
+use crate::process::process_in_memory;
+use crate::{input_arg, open_input, output_format};
+use act::config::Config;
+use act::output::RecordWriter;
+use act::reconcile::{read_expected, reconcile};
+use anyhow::{Result, bail};
+use clap::{Arg, ArgMatches, Command};
+use std::fs::File;
+use std::io::Write;
+
+pub fn command() -> Command {
+    Command::new("reconcile")
+        .about("Compare the resulting balances with an expected balances file")
+        .arg(
+            Arg::new("expected")
+                .long("expected")
+                .required(true)
+                .help("CSV file of client,available,held,total,locked"),
+        )
+        .arg(input_arg())
+}
+
+/// Processes the input and writes every balance differing from the expected
+/// ones, failing if there is any.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let path = matches
+        .get_one::<String>("expected")
+        .expect("expected is required");
+    let expected = read_expected(File::open(path)?)?;
+    let input = open_input(matches)?;
+    let act_store = process_in_memory(input, Vec::new(), config.policy).await?;
+
+    let discrepancies = reconcile(&expected, act_store.as_ref());
+    let mut writer = RecordWriter::new(std::io::stdout().lock(), output_format(matches, config));
+    for discrepancy in discrepancies.iter() {
+        writer.write(discrepancy)?;
+    }
+    writer.finish()?.flush()?;
+    if !discrepancies.is_empty() {
+        bail!("{} discrepancies", discrepancies.len());
+    }
+    eprintln!("Reconciled {} clients", act_store.count());
+    Ok(())
+}
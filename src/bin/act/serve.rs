@@ -0,0 +1,91 @@
+use crate::store_config;
+use act::config::Config;
+use act::engine::Engine;
+use act::server::{Ledger, grpc, http, tcp};
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+use log::info;
+
+pub fn command() -> Command {
+    Command::new("serve")
+        .about("Serve an HTTP API to submit transactions and query accounts")
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .default_value("127.0.0.1:8080")
+                .help("Address of the HTTP API"),
+        )
+        .arg(
+            Arg::new("tcp")
+                .long("tcp")
+                .help("Also accept CSV rows over TCP on this address"),
+        )
+        .arg(
+            Arg::new("grpc")
+                .long("grpc")
+                .help("Also serve the gRPC API on this address"),
+        )
+        .arg(
+            Arg::new("store")
+                .long("store")
+                .value_parser(["mem", "sqlite"])
+                .default_value("mem")
+                .help("Account store backend"),
+        )
+        .arg(
+            Arg::new("db")
+                .long("db")
+                .default_value("act.db")
+                .help("SQLite database file, used with --store sqlite"),
+        )
+}
+
+/// Serves the HTTP API, and the TCP ingestion and gRPC API if enabled,
+/// until one fails.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let store = store_config(matches, config)?;
+    let ledger = Ledger::from(
+        Engine::builder()
+            .stores(store.stores()?)
+            .policy(config.policy),
+    );
+    let listener = tokio::net::TcpListener::bind(
+        matches
+            .get_one::<String>("listen")
+            .expect("listen has a default"),
+    )
+    .await?;
+    info!("Listening on {}", listener.local_addr()?);
+    let ledger = ledger.shared();
+    let tcp_listener = match matches.get_one::<String>("tcp") {
+        Some(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            info!("Accepting CSV on {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+    let grpc_listener = match matches.get_one::<String>("grpc") {
+        Some(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            info!("Serving gRPC on {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+    // A disabled server never completes, the HTTP API always runs.
+    let tcp = async {
+        match tcp_listener {
+            Some(listener) => tcp::serve(listener, ledger.clone()).await,
+            None => std::future::pending().await,
+        }
+    };
+    let grpc = async {
+        match grpc_listener {
+            Some(listener) => grpc::serve(listener, ledger.clone()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::try_join!(http::serve(listener, ledger.clone()), tcp, grpc)?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use crate::{input_arg, open_input};
+use act::config::Config;
+use act::engine::Engine;
+use act::parse::parse_rows;
+use act::snapshot::Snapshot;
+use anyhow::{Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use std::io::Write;
+use std::path::Path;
+use tokio_stream::StreamExt;
+
+pub fn command() -> Command {
+    Command::new("snapshot")
+        .about("Process the input and save the resulting state to resume from later")
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .help("Snapshot to start from instead of empty stores"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("Snapshot file to write, stdout if omitted"),
+        )
+        .arg(input_arg())
+}
+
+/// Applies the input on top of an optional snapshot and writes the new one.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let snapshot = match matches.get_one::<String>("from") {
+        Some(path) => {
+            Snapshot::read(Path::new(path))?.with_context(|| format!("no snapshot at {}", path))?
+        }
+        None => Snapshot::default(),
+    };
+    let (act_store, tx_store) = snapshot.stores()?;
+    let mut engine = Engine::builder()
+        .act_store(Box::new(act_store))
+        .tx_store(Box::new(tx_store))
+        .policy(config.policy)
+        .build();
+    let rows = parse_rows(open_input(matches)?);
+    tokio::pin!(rows);
+    while let Some(row) = rows.next().await {
+        let rec = engine.apply_row(row)?;
+        if rec.tx.is_none() {
+            eprintln!(
+                "Error reading CSV: {}",
+                rec.reason.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    let snapshot = Snapshot::of(&engine)?;
+    match matches.get_one::<String>("output") {
+        Some(path) => snapshot.write(Path::new(path))?,
+        None => {
+            let mut out = std::io::stdout().lock();
+            serde_json::to_writer(&mut out, &snapshot)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
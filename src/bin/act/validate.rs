@@ -0,0 +1,49 @@
+use crate::{input_arg, open_input, output_format};
+use act::config::Config;
+use act::output::RecordWriter;
+use act::parse::parse_rows;
+use anyhow::{Result, bail};
+use clap::{ArgMatches, Command};
+use serde::Serialize;
+use std::io::Write;
+use tokio_stream::StreamExt;
+
+pub fn command() -> Command {
+    Command::new("validate")
+        .about("Check that every row of the input parses and fits the policy, without applying it")
+        .arg(input_arg())
+}
+
+#[derive(Serialize)]
+struct InvalidRow {
+    row: u64,
+    reason: String,
+}
+
+/// Writes the rows that do not parse or have more decimal places than the
+/// policy accepts, failing if there is any.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let rows = parse_rows(open_input(matches)?);
+    tokio::pin!(rows);
+    let mut writer = RecordWriter::new(std::io::stdout().lock(), output_format(matches, config));
+    let (mut total, mut invalid) = (0, 0);
+    while let Some(row) = rows.next().await {
+        total += 1;
+        let checked = row
+            .tx
+            .and_then(|t| config.policy.check_decimals(&t).map_err(|e| e.to_string()));
+        if let Err(reason) = checked {
+            writer.write(&InvalidRow {
+                row: row.row,
+                reason,
+            })?;
+            invalid += 1;
+        }
+    }
+    writer.finish()?.flush()?;
+    eprintln!("{} rows, {} invalid", total, invalid);
+    if invalid > 0 {
+        bail!("{} invalid rows", invalid);
+    }
+    Ok(())
+}
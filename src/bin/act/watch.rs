@@ -0,0 +1,85 @@
+use crate::output_format;
+use act::config::Config;
+use act::watch::{WatchOpts, Watcher};
+use anyhow::Result;
+use clap::{Arg, ArgAction::SetTrue, ArgMatches, Command};
+use log::info;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub fn command() -> Command {
+    Command::new("watch")
+        .about("Process the files dropped in a directory as they complete")
+        .arg(
+            Arg::new("dir")
+                .required(true)
+                .index(1)
+                .help("Inbox directory"),
+        )
+        .arg(
+            Arg::new("state")
+                .long("state")
+                .help("Snapshot of the engine, <dir>/state.json by default"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("Account output updated after each file, <dir>/accounts.csv by default"),
+        )
+        .arg(
+            Arg::new("settle")
+                .long("settle")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("5")
+                .help("Seconds a file must go unmodified before it is processed"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1")
+                .help("Seconds between scans of the directory"),
+        )
+        .arg(
+            Arg::new("once")
+                .long("once")
+                .action(SetTrue)
+                .help("Process the files waiting and exit"),
+        )
+}
+
+/// Processes the files of a directory, once or until an error.
+pub async fn run(matches: &ArgMatches, config: &Config) -> Result<()> {
+    let dir = PathBuf::from(matches.get_one::<String>("dir").expect("dir is required"));
+    let mut opts = WatchOpts::new(dir);
+    if let Some(state) = matches.get_one::<String>("state") {
+        opts.state = PathBuf::from(state);
+    }
+    if let Some(output) = matches.get_one::<String>("output") {
+        opts.output = PathBuf::from(output);
+    }
+    opts.format = output_format(matches, config);
+    opts.policy = config.policy;
+    opts.settle = Duration::from_secs(
+        *matches
+            .get_one::<u64>("settle")
+            .expect("settle has a default"),
+    );
+    let interval = Duration::from_secs(
+        *matches
+            .get_one::<u64>("interval")
+            .expect("interval has a default"),
+    );
+
+    let mut watcher = Watcher::open(opts)?;
+    if matches.get_flag("once") {
+        let applied = watcher.poll().await?;
+        eprintln!("Processed {} files", applied);
+        return Ok(());
+    }
+    info!(
+        "Watching {}",
+        matches.get_one::<String>("dir").expect("dir is required")
+    );
+    watcher.run(interval).await
+}
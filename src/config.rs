@@ -0,0 +1,248 @@
+use crate::engine::EngineBuilder;
+use crate::output::OutputFormat;
+use crate::process::Policy;
+use crate::stores::{
+    AccountOrder, ActStore, MemActStore, MemTxStore, SpillTxStore, SqliteActStore, TxStore,
+};
+use crate::types::amount::PRECISION;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Settings read from a TOML file. Every key is optional and defaults to
+/// the behaviour without a configuration file:
+///
+/// ```toml
+/// [policy]
+/// freeze_locked = false
+/// negative_chargeback = true
+/// negative_available = true
+/// dispute_withdrawals = false
+/// decimals = 4
+///
+/// [output]
+/// format = "csv"
+/// audit_format = "csv"
+/// journal_format = "csv"
+/// sort = "client"
+///
+/// [store]
+/// accounts = "mem"
+/// db = "act.db"
+/// transactions = "mem"
+/// tx_mem_budget = 256
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub policy: Policy,
+    pub output: OutputConfig,
+    pub store: StoreConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Format of the accounts and reports written to stdout
+    pub format: OutputFormat,
+    pub audit_format: OutputFormat,
+    pub journal_format: OutputFormat,
+    pub sort: AccountOrder,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    pub accounts: ActBackend,
+    /// SQLite database of the `sqlite` account store
+    pub db: PathBuf,
+    /// Transaction store of the `mem` account store, the `sqlite` one keeps
+    /// transactions in its database
+    pub transactions: TxBackend,
+    /// Memory budget of the `spill` transaction store in MiB
+    pub tx_mem_budget: usize,
+    /// Directory of the spilled transactions, a temporary one if None
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            accounts: ActBackend::Mem,
+            db: PathBuf::from("act.db"),
+            transactions: TxBackend::Mem,
+            tx_mem_budget: 256,
+            spill_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActBackend {
+    #[default]
+    Mem,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxBackend {
+    #[default]
+    Mem,
+    /// Bounded set in memory, the rest on disk
+    Spill,
+}
+
+impl Config {
+    /// Reads and validates a configuration file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("cannot read configuration {}", path.display()))?;
+        text.parse()
+            .with_context(|| format!("invalid configuration {}", path.display()))
+    }
+
+    /// Engine builder with the configured policy and stores.
+    pub fn engine<'a>(&self) -> Result<EngineBuilder<'a>> {
+        Ok(EngineBuilder::new()
+            .policy(self.policy)
+            .stores(self.store.stores()?))
+    }
+
+    /// Checks the values deserialization cannot.
+    fn validate(&self) -> Result<()> {
+        if self.policy.decimals > PRECISION {
+            bail!(
+                "policy.decimals: at most {} decimal places are supported",
+                PRECISION
+            );
+        }
+        if self.store.tx_mem_budget == 0 {
+            bail!("store.tx_mem_budget: must be at least 1");
+        }
+        self.store
+            .tx_mem_budget_bytes()
+            .map_err(|e| anyhow!("store.{}", e))?;
+        self.store
+            .check()
+            .map_err(|e| anyhow!("store.transactions: {}", e))
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    /// Errors name the offending key, e.g. `policy.decimals: invalid type`.
+    fn from_str(s: &str) -> Result<Self> {
+        let de = toml::Deserializer::parse(s)?;
+        let config: Config = serde_path_to_error::deserialize(de).map_err(|e| {
+            let message = e.inner().message().to_string();
+            match e.path().to_string().as_str() {
+                "." => anyhow!(message),
+                path => anyhow!("{}: {}", path, message),
+            }
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl StoreConfig {
+    /// Checks that the account and transaction stores can be combined.
+    pub fn check(&self) -> Result<()> {
+        if self.accounts == ActBackend::Sqlite && self.transactions == TxBackend::Spill {
+            bail!(
+                "the sqlite account store keeps transactions in its database, spill is not supported"
+            );
+        }
+        Ok(())
+    }
+
+    /// Memory budget of the spill store in bytes.
+    pub fn tx_mem_budget_bytes(&self) -> Result<usize> {
+        self.tx_mem_budget
+            .checked_mul(1024 * 1024)
+            .ok_or_else(|| anyhow!("tx_mem_budget: {} MiB is too large", self.tx_mem_budget))
+    }
+
+    /// Account and transaction stores, the sqlite ones sharing a connection.
+    pub fn stores(&self) -> Result<(Box<dyn ActStore + Send>, Box<dyn TxStore + Send>)> {
+        self.check()?;
+        if self.accounts == ActBackend::Sqlite {
+            let act_store = SqliteActStore::open(&self.db)?;
+            let tx_store = act_store.tx_store();
+            return Ok((Box::new(act_store), Box::new(tx_store)));
+        }
+        let tx_store: Box<dyn TxStore + Send> = match self.transactions {
+            TxBackend::Mem => Box::new(MemTxStore::new()),
+            TxBackend::Spill => {
+                let budget = self.tx_mem_budget_bytes()?;
+                Box::new(match &self.spill_dir {
+                    Some(dir) => SpillTxStore::new(dir, budget)?,
+                    None => SpillTxStore::temporary(budget)?,
+                })
+            }
+        };
+        Ok((Box::new(MemActStore::new()), tx_store))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = r#"
+            [policy]
+            dispute_withdrawals = true
+            decimals = 2
+
+            [output]
+            format = "jsonl"
+
+            [store]
+            transactions = "spill"
+        "#
+        .parse()
+        .unwrap();
+        assert!(config.policy.dispute_withdrawals);
+        assert_eq!(2, config.policy.decimals);
+        assert!(config.policy.negative_chargeback);
+        assert_eq!(OutputFormat::Jsonl, config.output.format);
+        assert_eq!(OutputFormat::Csv, config.output.audit_format);
+        assert_eq!(TxBackend::Spill, config.store.transactions);
+        assert_eq!(Config::default(), "".parse().unwrap());
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let error = |s: &str| s.parse::<Config>().unwrap_err().to_string();
+        assert_eq!(
+            "policy.freeze_locked: invalid type: string \"yes\", expected a boolean",
+            error("[policy]\nfreeze_locked = \"yes\"")
+        );
+        assert!(error("[store]\naccounts = \"postgres\"").starts_with("store.accounts: "));
+        assert!(error("[output]\ncolour = true").starts_with("output.colour: unknown field"));
+        assert!(error("[outputs]").starts_with("outputs: unknown field"));
+        assert_eq!(
+            "policy.decimals: at most 4 decimal places are supported",
+            error("[policy]\ndecimals = 6")
+        );
+        assert!(
+            error("[store]\naccounts = \"sqlite\"\ntransactions = \"spill\"")
+                .starts_with("store.transactions: the sqlite account store")
+        );
+        assert_eq!(
+            "store.tx_mem_budget: must be at least 1",
+            error("[store]\ntx_mem_budget = 0")
+        );
+        assert_eq!(
+            format!("store.tx_mem_budget: {} MiB is too large", usize::MAX / 2),
+            error(&format!("[store]\ntx_mem_budget = {}", usize::MAX / 2))
+        );
+    }
+}
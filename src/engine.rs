@@ -0,0 +1,258 @@
+use crate::audit::{AuditRecord, AuditSink};
+use crate::output::{OutputFormat, RecordWriter};
+use crate::parse::Row;
+use crate::process::{Policy, process_audited_with};
+use crate::stores::{ActStore, MemActStore, MemTxStore, TxStore};
+use crate::summary::Counts;
+use crate::types::account::AccountSer;
+use crate::types::{Account, Transaction};
+use anyhow::Result;
+use log::warn;
+use std::io::Write;
+use tokio_stream::{Stream, StreamExt};
+
+/// Applies transactions one at a time to its stores and hands the outcome of
+/// each to its sinks.
+///
+/// ```
+/// # use act::engine::Engine;
+/// # use act::types::{Transaction, TransactionType};
+/// let mut engine = Engine::builder().build();
+/// let t = Transaction {
+///     tx_type: TransactionType::Deposit,
+///     client: 1,
+///     tx: 1,
+///     amount: 10000,
+/// };
+/// assert!(engine.apply(t).unwrap().applied());
+/// assert_eq!(10000, engine.account(1).unwrap().total());
+/// ```
+pub struct Engine<'a> {
+    act_store: Box<dyn ActStore + Send>,
+    tx_store: Box<dyn TxStore + Send>,
+    policy: Policy,
+    sinks: Vec<Box<dyn AuditSink + Send + 'a>>,
+    /// Rows applied so far, numbers the audit records
+    rows: u64,
+}
+
+/// Configures an [`Engine`], stores are in memory unless chosen.
+#[derive(Default)]
+pub struct EngineBuilder<'a> {
+    act_store: Option<Box<dyn ActStore + Send>>,
+    tx_store: Option<Box<dyn TxStore + Send>>,
+    policy: Policy,
+    sinks: Vec<Box<dyn AuditSink + Send + 'a>>,
+}
+
+impl<'a> EngineBuilder<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn act_store(mut self, act_store: Box<dyn ActStore + Send>) -> Self {
+        self.act_store = Some(act_store);
+        self
+    }
+
+    pub fn tx_store(mut self, tx_store: Box<dyn TxStore + Send>) -> Self {
+        self.tx_store = Some(tx_store);
+        self
+    }
+
+    /// Sets both stores, e.g. as returned by [`crate::config::StoreConfig::stores`].
+    pub fn stores(
+        self,
+        (act_store, tx_store): (Box<dyn ActStore + Send>, Box<dyn TxStore + Send>),
+    ) -> Self {
+        self.act_store(act_store).tx_store(tx_store)
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Adds a sink receiving the record of every row, in the order sinks
+    /// are added. A sink borrowed with `&mut` can be read once the engine
+    /// is dropped.
+    pub fn sink(mut self, sink: impl AuditSink + Send + 'a) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn build(self) -> Engine<'a> {
+        Engine {
+            act_store: self
+                .act_store
+                .unwrap_or_else(|| Box::new(MemActStore::new())),
+            tx_store: self.tx_store.unwrap_or_else(|| Box::new(MemTxStore::new())),
+            policy: self.policy,
+            sinks: self.sinks,
+            rows: 0,
+        }
+    }
+}
+
+impl<'a> Engine<'a> {
+    pub fn builder() -> EngineBuilder<'a> {
+        EngineBuilder::new()
+    }
+
+    /// Applies a transaction as the next row. Fails only if a sink fails,
+    /// a rejected transaction is reported in the record.
+    pub fn apply(&mut self, t: Transaction) -> Result<AuditRecord> {
+        let row = self.rows + 1;
+        self.apply_row(Row {
+            row,
+            tx: Ok(t),
+            timestamp: None,
+        })
+    }
+
+    /// Applies a parsed row, rows that could not be parsed are recorded as
+    /// rejected.
+    pub fn apply_row(&mut self, row: Row) -> Result<AuditRecord> {
+        self.rows = row.row;
+        let rec = match row.tx {
+            Ok(t) => process_audited_with(
+                row.row,
+                t,
+                self.act_store.as_mut(),
+                self.tx_store.as_mut(),
+                &self.policy,
+            ),
+            Err(e) => AuditRecord::invalid(row.row, e),
+        }
+        .with_timestamp(row.timestamp);
+        if let Some(reason) = &rec.reason {
+            warn!("Row {} rejected: {}", rec.row, reason);
+        }
+        for sink in self.sinks.iter_mut() {
+            sink.record(&rec)?;
+        }
+        Ok(rec)
+    }
+
+    /// Applies every transaction of the stream, e.g. from
+    /// [`crate::parse::parse`], in order.
+    pub async fn apply_stream<S: Stream<Item = Transaction>>(&mut self, txs: S) -> Result<Counts> {
+        let mut counts = Counts::default();
+        tokio::pin!(txs);
+        while let Some(t) = txs.next().await {
+            match self.apply(t)?.applied() {
+                true => counts.applied += 1,
+                false => counts.rejected += 1,
+            }
+        }
+        Ok(counts)
+    }
+
+    pub fn account(&self, client: u16) -> Option<&Account> {
+        self.act_store.get_account(client)
+    }
+
+    /// Accounts ordered by client id.
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.act_store.accounts()
+    }
+
+    pub fn act_store(&self) -> &dyn ActStore {
+        self.act_store.as_ref()
+    }
+
+    pub fn tx_store(&self) -> &dyn TxStore {
+        self.tx_store.as_ref()
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Number of the last row applied.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Writes every account ordered by client id, as the command line does.
+    pub fn export<W: Write>(&self, writer: W, format: OutputFormat) -> Result<W> {
+        let mut writer = RecordWriter::new(writer, format);
+        for act in self.accounts() {
+            writer.write(&AccountSer::from(act))?;
+        }
+        writer.finish()
+    }
+
+    /// Stores of the engine, the sinks are dropped.
+    pub fn into_stores(self) -> (Box<dyn ActStore + Send>, Box<dyn TxStore + Send>) {
+        (self.act_store, self.tx_store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use crate::summary::Summary;
+    use crate::types::TransactionType;
+
+    #[tokio::test]
+    async fn test_apply_stream() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,1.0\n\
+                     deposit,2,2,2.0\n\
+                     withdrawal,1,3,1.5\n\
+                     dispute,2,2,\n";
+        let mut summary = Summary::new();
+        let mut engine = Engine::builder().sink(&mut summary).build();
+        let counts = engine.apply_stream(parse(input.as_bytes())).await.unwrap();
+        assert_eq!(
+            Counts {
+                applied: 3,
+                rejected: 1
+            },
+            counts
+        );
+        assert_eq!(20000, engine.account(2).unwrap().held());
+        let out = engine.export(Vec::new(), OutputFormat::Csv).unwrap();
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,1.0000,0.0000,1.0000,false\n\
+             2,0.0000,2.0000,2.0000,false\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let (act_store, _) = engine.into_stores();
+        assert_eq!(4, summary.report(act_store.as_ref()).rows);
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut engine = Engine::builder()
+            .policy(Policy {
+                freeze_locked: true,
+                ..Default::default()
+            })
+            .build();
+        let txs = [
+            (TransactionType::Deposit, 1, 10000),
+            (TransactionType::Dispute, 1, 0),
+            (TransactionType::Chargeback, 1, 0),
+            (TransactionType::Deposit, 2, 10000),
+        ];
+        let applied: Vec<bool> = txs
+            .into_iter()
+            .map(|(tx_type, tx, amount)| {
+                let t = Transaction {
+                    tx_type,
+                    client: 1,
+                    tx,
+                    amount,
+                };
+                engine.apply(t).unwrap().applied()
+            })
+            .collect();
+        assert_eq!(vec![true, true, true, false], applied);
+        assert_eq!(0, engine.account(1).unwrap().total());
+    }
+}
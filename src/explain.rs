@@ -0,0 +1,258 @@
+use crate::audit::{AuditRecord, AuditSink, Outcome};
+use crate::types::TransactionType;
+use crate::types::amount::format_amount;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// One step of a client's history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerLine {
+    pub row: u64,
+    pub tx_type: TransactionType,
+    /// Client of the row, differs from the explained client when another
+    /// client references one of its transactions.
+    pub client: u16,
+    pub tx: u32,
+    /// Amount moved, for disputes, resolves and chargebacks the amount of
+    /// the referenced transaction if known.
+    pub amount: Option<u64>,
+    /// Type and row of the deposit or withdrawal referenced by a dispute,
+    /// resolve or chargeback
+    pub referenced: Option<(TransactionType, u64)>,
+    pub outcome: Outcome,
+    pub reason: Option<String>,
+    pub available: i128,
+    pub held: u64,
+    pub total: i64,
+    pub locked: bool,
+}
+
+/// Collects every row touching a client, including rows of other clients
+/// referencing its transactions, with the running state of its account.
+pub struct Explainer {
+    client: u16,
+    /// Applied deposits and withdrawals of the client: tx id to
+    /// (type, row, amount)
+    transactions: HashMap<u32, (TransactionType, u64, u64)>,
+    lines: Vec<LedgerLine>,
+}
+
+impl Explainer {
+    pub fn new(client: u16) -> Self {
+        Explainer {
+            client,
+            transactions: HashMap::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    pub fn lines(&self) -> &[LedgerLine] {
+        &self.lines
+    }
+
+    /// Writes the ledger as a text table.
+    pub fn write_text<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(w, "Client {}", self.client)?;
+        writeln!(
+            w,
+            "{:>8}  {:<10}  {:>10}  {:>14}  {:<8}  {:>14}  {:>14}  {:>14}  {:<6}  note",
+            "row", "type", "tx", "amount", "outcome", "available", "held", "total", "locked"
+        )?;
+        for line in self.lines.iter() {
+            let mut notes = Vec::new();
+            if line.client != self.client {
+                notes.push(format!("submitted by client {}", line.client));
+            }
+            if let Some((tx_type, row)) = line.referenced {
+                notes.push(format!("{:?} of row {}", tx_type, row).to_lowercase());
+            }
+            if let Some(reason) = &line.reason {
+                notes.push(reason.clone());
+            }
+            let text = format!(
+                "{:>8}  {:<10}  {:>10}  {:>14}  {:<8}  {:>14}  {:>14}  {:>14}  {:<6}  {}",
+                line.row,
+                format!("{:?}", line.tx_type).to_lowercase(),
+                line.tx,
+                line.amount.map(format_amount).unwrap_or_default(),
+                format!("{:?}", line.outcome).to_lowercase(),
+                format_amount(line.available),
+                format_amount(line.held),
+                format_amount(line.total),
+                line.locked,
+                notes.join("; ")
+            );
+            writeln!(w, "{}", text.trim_end())?;
+        }
+        let (applied, rejected) = self
+            .lines
+            .iter()
+            .partition::<Vec<&LedgerLine>, _>(|line| line.outcome == Outcome::Applied);
+        writeln!(
+            w,
+            "{} rows, {} applied, {} rejected",
+            self.lines.len(),
+            applied.len(),
+            rejected.len()
+        )?;
+        Ok(())
+    }
+}
+
+impl AuditSink for Explainer {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        let Some(t) = &rec.tx else {
+            return Ok(());
+        };
+        let referenced = match t.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                if rec.applied() && t.client == self.client {
+                    self.transactions
+                        .insert(t.tx, (t.tx_type, rec.row, t.amount));
+                }
+                None
+            }
+            _ => self.transactions.get(&t.tx).copied(),
+        };
+        if t.client != self.client && referenced.is_none() {
+            return Ok(());
+        }
+        // Rows of other clients leave the account untouched.
+        let (available, held, total, locked) = match (t.client == self.client, &rec.after) {
+            (true, Some(act)) => (
+                act.available_exact(),
+                act.held(),
+                act.total(),
+                act.is_locked(),
+            ),
+            (_, _) => self.lines.last().map_or((0, 0, 0, false), |l| {
+                (l.available, l.held, l.total, l.locked)
+            }),
+        };
+        self.lines.push(LedgerLine {
+            row: rec.row,
+            tx_type: t.tx_type,
+            client: t.client,
+            tx: t.tx,
+            amount: match t.tx_type {
+                TransactionType::Deposit | TransactionType::Withdrawal => Some(t.amount),
+                _ => referenced.map(|(_, _, amount)| amount),
+            },
+            referenced: referenced.map(|(tx_type, row, _)| (tx_type, row)),
+            outcome: rec.outcome,
+            reason: rec.reason.clone(),
+            available,
+            held,
+            total,
+            locked,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Fixture, TxRow, records};
+    use crate::process::Policy;
+
+    fn explain(client: u16, txs: &[TxRow]) -> Explainer {
+        let mut explainer = Explainer::new(client);
+        for rec in records(txs) {
+            explainer.record(&rec).unwrap();
+        }
+        explainer
+    }
+
+    #[test]
+    fn test_tracks_client_rows() {
+        let explainer = explain(
+            1,
+            &[
+                (TransactionType::Deposit, 1, 1, 10000),
+                (TransactionType::Deposit, 2, 2, 10000),
+                (TransactionType::Withdrawal, 1, 3, 20000),
+                (TransactionType::Dispute, 2, 1, 0),
+                (TransactionType::Dispute, 1, 1, 0),
+                (TransactionType::Chargeback, 1, 1, 0),
+            ],
+        );
+        let lines = explainer.lines();
+        assert_eq!(
+            vec![1, 3, 4, 5, 6],
+            lines.iter().map(|l| l.row).collect::<Vec<_>>()
+        );
+        assert_eq!(Outcome::Rejected, lines[1].outcome);
+        assert!(lines[1].reason.is_some());
+        assert_eq!(10000, lines[1].available);
+
+        // Dispute of another client referencing the deposit of row 1
+        assert_eq!(2, lines[2].client);
+        assert_eq!(Some((TransactionType::Deposit, 1)), lines[2].referenced);
+        assert_eq!(Outcome::Rejected, lines[2].outcome);
+        assert_eq!(10000, lines[2].available);
+
+        assert_eq!(Some(10000), lines[3].amount);
+        assert_eq!(10000, lines[3].held);
+        assert_eq!(0, lines[4].total);
+        assert!(lines[4].locked);
+    }
+
+    #[test]
+    fn test_write_text() {
+        let explainer = explain(
+            1,
+            &[
+                (TransactionType::Deposit, 1, 1, 10000),
+                (TransactionType::Dispute, 1, 1, 0),
+                (TransactionType::Resolve, 1, 2, 0),
+            ],
+        );
+        let mut out = Vec::new();
+        explainer.write_text(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(6, lines.len());
+        assert_eq!("Client 1", lines[0]);
+        assert!(lines[2].contains("deposit"));
+        assert!(lines[3].contains("deposit of row 1"));
+        assert!(lines[4].contains("Resolve: Transaction not found in store"));
+        assert_eq!("3 rows, 2 applied, 1 rejected", lines[5]);
+    }
+
+    #[test]
+    fn test_withdrawal_disputes() {
+        let mut explainer = Explainer::new(1);
+        let mut fixture = Fixture::with_policy(Policy {
+            dispute_withdrawals: true,
+            ..Default::default()
+        });
+        let rows = [
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Withdrawal, 1, 2, 4000),
+            (TransactionType::Dispute, 1, 2, 0),
+            (TransactionType::Chargeback, 1, 2, 0),
+        ];
+        for rec in fixture.apply_all(&rows) {
+            explainer.record(&rec).unwrap();
+        }
+        let lines = explainer.lines();
+        for line in &lines[2..] {
+            assert_eq!(Some(4000), line.amount);
+            assert_eq!(Some((TransactionType::Withdrawal, 2)), line.referenced);
+        }
+        assert_eq!((10000, 0), (lines[3].total, lines[3].held));
+        let mut out = Vec::new();
+        explainer.write_text(&mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("withdrawal of row 2")
+        );
+    }
+}
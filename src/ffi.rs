@@ -0,0 +1,405 @@
+//! C API of the engine, see `include/act.h`. Every function taking a
+//! pointer accepts null and answers it with `ACT_STATUS_INVALID`, null or
+//! zero. Amounts are integer units of 0.0001.
+
+use crate::engine::Engine;
+use crate::parse::RecordParser;
+use crate::stores::SqliteActStore;
+use crate::types::{Account, Transaction, TransactionType};
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+pub const ACT_DEPOSIT: u32 = 0;
+pub const ACT_WITHDRAWAL: u32 = 1;
+pub const ACT_DISPUTE: u32 = 2;
+pub const ACT_RESOLVE: u32 = 3;
+pub const ACT_CHARGEBACK: u32 = 4;
+
+/// Result of a call, `act_last_error` describes anything but `Ok`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActStatus {
+    Ok = 0,
+    /// The transaction was valid but not applied, e.g. insufficient funds
+    Rejected = 1,
+    /// Null pointer, unknown transaction type or unparsable CSV line
+    Invalid = 2,
+    NotFound = 3,
+    Error = 4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActAccount {
+    pub client: u16,
+    pub available: i64,
+    pub held: u64,
+    pub total: i64,
+    pub locked: bool,
+}
+
+impl From<&Account> for ActAccount {
+    fn from(act: &Account) -> Self {
+        ActAccount {
+            client: act.id(),
+            available: act.total().saturating_sub_unsigned(act.held()),
+            held: act.held(),
+            total: act.total(),
+            locked: act.is_locked(),
+        }
+    }
+}
+
+/// Opaque engine handle.
+pub struct ActEngine {
+    engine: Engine<'static>,
+    parser: RecordParser,
+    last_error: Option<CString>,
+}
+
+impl ActEngine {
+    fn new(engine: Engine<'static>) -> Box<Self> {
+        Box::new(ActEngine {
+            engine,
+            parser: RecordParser::from_header_line("type,client,tx,amount")
+                .expect("default header parses"),
+            last_error: None,
+        })
+    }
+
+    fn fail(&mut self, status: ActStatus, message: impl ToString) -> ActStatus {
+        // Messages do not contain NUL bytes, drop them rather than the message.
+        let message = message.to_string().replace('\0', "");
+        self.last_error = CString::new(message).ok();
+        status
+    }
+}
+
+/// Snapshot of the accounts of an engine, ordered by client id.
+pub struct ActAccountIter {
+    accounts: std::vec::IntoIter<ActAccount>,
+}
+
+/// Engine with in-memory stores, free with `act_engine_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn act_engine_new() -> *mut ActEngine {
+    Box::into_raw(ActEngine::new(Engine::builder().build()))
+}
+
+/// Engine keeping accounts and transactions in a SQLite database, null if
+/// it cannot be opened.
+///
+/// # Safety
+///
+/// `path` must be null or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_engine_open_sqlite(path: *const c_char) -> *mut ActEngine {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: checked for null, NUL-terminated per the contract.
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return ptr::null_mut();
+    };
+    match SqliteActStore::open(path) {
+        Ok(act_store) => {
+            let tx_store = act_store.tx_store();
+            Box::into_raw(ActEngine::new(
+                Engine::builder()
+                    .act_store(Box::new(act_store))
+                    .tx_store(Box::new(tx_store))
+                    .build(),
+            ))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `engine` must be null or returned by `act_engine_new` or
+/// `act_engine_open_sqlite` and not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_engine_free(engine: *mut ActEngine) {
+    if !engine.is_null() {
+        // SAFETY: allocated by Box::into_raw and not freed yet per the contract.
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// Applies a transaction, `amount` is ignored for disputes, resolves and
+/// chargebacks.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_apply(
+    engine: *mut ActEngine,
+    tx_type: u32,
+    client: u16,
+    tx: u32,
+    amount: u64,
+) -> ActStatus {
+    // SAFETY: null or live per the contract.
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return ActStatus::Invalid;
+    };
+    let tx_type = match tx_type {
+        ACT_DEPOSIT => TransactionType::Deposit,
+        ACT_WITHDRAWAL => TransactionType::Withdrawal,
+        ACT_DISPUTE => TransactionType::Dispute,
+        ACT_RESOLVE => TransactionType::Resolve,
+        ACT_CHARGEBACK => TransactionType::Chargeback,
+        _ => {
+            return engine.fail(
+                ActStatus::Invalid,
+                format!("Unknown transaction type {}", tx_type),
+            );
+        }
+    };
+    let t = Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+    };
+    let row = engine.engine.rows() + 1;
+    apply_row(engine, row, Ok(t))
+}
+
+/// Applies a CSV line with the columns `type,client,tx,amount`, parsed with
+/// the rules of input files.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine, `line` null or a NUL-terminated
+/// string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_apply_csv(engine: *mut ActEngine, line: *const c_char) -> ActStatus {
+    // SAFETY: null or live per the contract.
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return ActStatus::Invalid;
+    };
+    if line.is_null() {
+        return engine.fail(ActStatus::Invalid, "Null line");
+    }
+    // SAFETY: checked for null, NUL-terminated per the contract.
+    let line = match unsafe { CStr::from_ptr(line) }.to_str() {
+        Ok(line) => line,
+        Err(e) => return engine.fail(ActStatus::Invalid, e),
+    };
+    let row = engine.engine.rows() + 1;
+    match engine.parser.parse_line(row, line) {
+        Some(parsed) => apply_row(engine, row, parsed.tx),
+        None => engine.fail(ActStatus::Invalid, "Empty line"),
+    }
+}
+
+fn apply_row(engine: &mut ActEngine, row: u64, tx: Result<Transaction, String>) -> ActStatus {
+    let parsed = tx.is_ok();
+    let rec = engine.engine.apply_row(crate::parse::Row {
+        row,
+        tx,
+        timestamp: None,
+    });
+    match rec {
+        Ok(rec) if rec.applied() => {
+            engine.last_error = None;
+            ActStatus::Ok
+        }
+        Ok(rec) => {
+            let status = match parsed {
+                true => ActStatus::Rejected,
+                false => ActStatus::Invalid,
+            };
+            engine.fail(status, rec.reason.unwrap_or_default())
+        }
+        Err(e) => engine.fail(ActStatus::Error, e),
+    }
+}
+
+/// Message of the last call that did not return `ACT_STATUS_OK`, null if
+/// the last call succeeded. Valid until the next call on the engine.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_last_error(engine: *const ActEngine) -> *const c_char {
+    // SAFETY: null or live per the contract.
+    match unsafe { engine.as_ref() }.and_then(|engine| engine.last_error.as_ref()) {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Writes the account of `client` to `out`.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine, `out` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_get_account(
+    engine: *mut ActEngine,
+    client: u16,
+    out: *mut ActAccount,
+) -> ActStatus {
+    // SAFETY: null or live per the contract.
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return ActStatus::Invalid;
+    };
+    if out.is_null() {
+        return engine.fail(ActStatus::Invalid, "Null account");
+    }
+    match engine.engine.account(client).map(ActAccount::from) {
+        Some(act) => {
+            // SAFETY: checked for null, writable per the contract.
+            unsafe { out.write(act) };
+            engine.last_error = None;
+            ActStatus::Ok
+        }
+        None => engine.fail(ActStatus::NotFound, format!("Unknown client {}", client)),
+    }
+}
+
+/// Snapshot of every account, unaffected by later transactions. Free with
+/// `act_accounts_free`.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_accounts(engine: *const ActEngine) -> *mut ActAccountIter {
+    // SAFETY: null or live per the contract.
+    let Some(engine) = (unsafe { engine.as_ref() }) else {
+        return ptr::null_mut();
+    };
+    let accounts: Vec<ActAccount> = engine.engine.accounts().map(ActAccount::from).collect();
+    Box::into_raw(Box::new(ActAccountIter {
+        accounts: accounts.into_iter(),
+    }))
+}
+
+/// Writes the next account to `out`, false once every account was read.
+///
+/// # Safety
+///
+/// `iter` must be null or returned by `act_accounts` and not freed yet,
+/// `out` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_accounts_next(
+    iter: *mut ActAccountIter,
+    out: *mut ActAccount,
+) -> bool {
+    // SAFETY: null or live per the contract.
+    let Some(iter) = (unsafe { iter.as_mut() }) else {
+        return false;
+    };
+    if out.is_null() {
+        return false;
+    }
+    match iter.accounts.next() {
+        Some(act) => {
+            // SAFETY: checked for null, writable per the contract.
+            unsafe { out.write(act) };
+            true
+        }
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// `iter` must be null or returned by `act_accounts` and not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn act_accounts_free(iter: *mut ActAccountIter) {
+    if !iter.is_null() {
+        // SAFETY: allocated by Box::into_raw and not freed yet per the contract.
+        drop(unsafe { Box::from_raw(iter) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error(engine: *const ActEngine) -> String {
+        let message = unsafe { act_last_error(engine) };
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_apply_and_query() {
+        let engine = act_engine_new();
+        unsafe {
+            assert_eq!(ActStatus::Ok, act_apply(engine, ACT_DEPOSIT, 1, 1, 20000));
+            assert!(act_last_error(engine).is_null());
+            assert_eq!(
+                ActStatus::Ok,
+                act_apply_csv(engine, c"deposit, 2, 2, 1.5".as_ptr())
+            );
+            assert_eq!(
+                ActStatus::Rejected,
+                act_apply(engine, ACT_WITHDRAWAL, 1, 3, 30000)
+            );
+            assert!(last_error(engine).contains("negative balance"));
+            assert_eq!(
+                ActStatus::Invalid,
+                act_apply_csv(engine, c"deposit,1,4,1.00001".as_ptr())
+            );
+            assert!(last_error(engine).contains("precision"));
+            assert_eq!(ActStatus::Invalid, act_apply(engine, 9, 1, 5, 0));
+            assert_eq!(
+                ActStatus::Ok,
+                act_apply_csv(engine, c"dispute,1,1,".as_ptr())
+            );
+
+            let mut act = ActAccount {
+                client: 0,
+                available: 0,
+                held: 0,
+                total: 0,
+                locked: false,
+            };
+            assert_eq!(ActStatus::Ok, act_get_account(engine, 1, &mut act));
+            assert_eq!(
+                ActAccount {
+                    client: 1,
+                    available: 0,
+                    held: 20000,
+                    total: 20000,
+                    locked: false,
+                },
+                act
+            );
+            assert_eq!(ActStatus::NotFound, act_get_account(engine, 7, &mut act));
+
+            let iter = act_accounts(engine);
+            let mut clients = Vec::new();
+            while act_accounts_next(iter, &mut act) {
+                clients.push((act.client, act.total));
+            }
+            act_accounts_free(iter);
+            assert_eq!(vec![(1, 20000), (2, 15000)], clients);
+            act_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        unsafe {
+            assert_eq!(
+                ActStatus::Invalid,
+                act_apply(ptr::null_mut(), ACT_DEPOSIT, 1, 1, 1)
+            );
+            assert!(act_last_error(ptr::null()).is_null());
+            assert!(act_accounts(ptr::null()).is_null());
+            assert!(act_engine_open_sqlite(ptr::null()).is_null());
+            act_engine_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Rows and stores shared by the tests of the audit sinks.
+
+use crate::audit::AuditRecord;
+use crate::process::{Policy, process_audited_with};
+use crate::stores::{MemActStore, MemTxStore};
+use crate::types::{Account, Transaction, TransactionType};
+
+/// Type, client, tx and amount of an input row.
+pub type TxRow = (TransactionType, u16, u32, u64);
+
+/// In-memory stores applying rows numbered from 1 in the order given.
+#[derive(Default)]
+pub struct Fixture {
+    pub act_store: MemActStore,
+    pub tx_store: MemTxStore,
+    pub policy: Policy,
+    rows: u64,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_policy(policy: Policy) -> Self {
+        Fixture {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Stores starting with the accounts, e.g. of an earlier run.
+    pub fn with_accounts<I: IntoIterator<Item = Account>>(accounts: I) -> Self {
+        Fixture {
+            act_store: accounts.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn apply(&mut self, (tx_type, client, tx, amount): TxRow) -> AuditRecord {
+        self.rows += 1;
+        let t = Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+        process_audited_with(
+            self.rows,
+            t,
+            &mut self.act_store,
+            &mut self.tx_store,
+            &self.policy,
+        )
+    }
+
+    pub fn apply_all(&mut self, rows: &[TxRow]) -> Vec<AuditRecord> {
+        rows.iter().map(|row| self.apply(*row)).collect()
+    }
+}
+
+/// Records of the rows applied to empty stores.
+pub fn records(rows: &[TxRow]) -> Vec<AuditRecord> {
+    Fixture::new().apply_all(rows)
+}
@@ -0,0 +1,216 @@
+use crate::parse::{RecordParser, Row};
+use anyhow::Result;
+use std::fs::{self, File, Metadata};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Most bytes read by one poll, so that catching up on a large file does not
+/// hold all of it in memory.
+const READ_CHUNK: u64 = 1 << 20;
+
+/// Reads the rows of a CSV file as they are appended, like `tail -f`.
+///
+/// Rows are only parsed once their line is complete. A file replaced by a
+/// new one, e.g. by log rotation, is read to its end before the new file is
+/// opened, a file truncated in place is read again from its start. Every
+/// file starts with a header, rows are numbered across files. A poll reads
+/// at most [`READ_CHUNK`] bytes, see [`Follower::behind`].
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+    /// Bytes of the current file read so far
+    offset: u64,
+    /// Start of a line not terminated yet
+    partial: Vec<u8>,
+    /// None until the header of the current file is read
+    parser: Option<RecordParser>,
+    row: u64,
+    /// Most bytes read by a poll
+    chunk: u64,
+    /// Whether the last read stopped before the end of the file
+    behind: bool,
+}
+
+impl Follower {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        Ok(Follower {
+            path: path.to_path_buf(),
+            file,
+            id,
+            offset: 0,
+            partial: Vec::new(),
+            parser: None,
+            row: 0,
+            chunk: READ_CHUNK,
+            behind: false,
+        })
+    }
+
+    /// Whether the last poll stopped before the end of the file, the next
+    /// one then has rows without waiting for the writer.
+    pub fn behind(&self) -> bool {
+        self.behind
+    }
+
+    /// Rows completed since the last call.
+    pub fn poll(&mut self) -> Result<Vec<Row>> {
+        let mut rows = Vec::new();
+        self.read(&mut rows)?;
+        if self.behind {
+            return Ok(rows);
+        }
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            // Rotated away, the new file is not there yet.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(rows),
+            Err(e) => return Err(e.into()),
+        };
+        if file_id(&meta) != self.id {
+            // The writer is done with the old file, its last line is complete.
+            if !self.partial.is_empty() {
+                let line = std::mem::take(&mut self.partial);
+                self.line(&line, &mut rows)?;
+            }
+            *self = Follower {
+                row: self.row,
+                chunk: self.chunk,
+                ..Follower::open(&self.path)?
+            };
+            self.read(&mut rows)?;
+        } else if meta.len() < self.offset {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+            self.partial.clear();
+            self.parser = None;
+            self.read(&mut rows)?;
+        }
+        Ok(rows)
+    }
+
+    /// Reads the current file up to its end, at most a chunk of it.
+    fn read(&mut self, rows: &mut Vec<Row>) -> Result<()> {
+        let read = (&mut self.file)
+            .take(self.chunk)
+            .read_to_end(&mut self.partial)? as u64;
+        self.offset += read;
+        self.behind = read == self.chunk;
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            self.line(&line, rows)?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &[u8], rows: &mut Vec<Row>) -> Result<()> {
+        let line = String::from_utf8_lossy(line);
+        let Some(parser) = &self.parser else {
+            if !line.trim().is_empty() {
+                self.parser = Some(RecordParser::from_header_line(&line)?);
+            }
+            return Ok(());
+        };
+        if let Some(row) = parser.parse_line(self.row + 1, &line) {
+            self.row += 1;
+            rows.push(row);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
+}
+
+/// Rotation is only detected through truncation.
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<FileId> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn append(path: &Path, data: &str) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn txs(rows: Vec<Row>) -> Vec<(u64, u32)> {
+        rows.into_iter()
+            .map(|row| (row.row, row.tx.unwrap().tx))
+            .collect()
+    }
+
+    #[test]
+    fn test_partial_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("in.csv");
+        append(&path, "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2");
+        let mut follower = Follower::open(&path).unwrap();
+        assert_eq!(vec![(1, 1)], txs(follower.poll().unwrap()));
+        assert!(follower.poll().unwrap().is_empty());
+        append(&path, ",2.0\n\ndeposit,1,3,1.0\n");
+        assert_eq!(vec![(2, 2), (3, 3)], txs(follower.poll().unwrap()));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("in.csv");
+        append(&path, "type,client,tx,amount\ndeposit,1,1,1.0\n");
+        let mut follower = Follower::open(&path).unwrap();
+        assert_eq!(vec![(1, 1)], txs(follower.poll().unwrap()));
+
+        // Written before the rotation, without a final newline.
+        append(&path, "deposit,1,2,1.0");
+        fs::rename(&path, dir.path().join("in.csv.1")).unwrap();
+        assert!(follower.poll().unwrap().is_empty());
+        append(&path, "type,client,tx,amount\ndeposit,1,3,1.0\n");
+        assert_eq!(vec![(2, 2), (3, 3)], txs(follower.poll().unwrap()));
+
+        // Truncated in place.
+        fs::write(&path, "type,client,tx,amount\n").unwrap();
+        assert!(follower.poll().unwrap().is_empty());
+        append(&path, "deposit,1,4,1.0\n");
+        assert_eq!(vec![(4, 4)], txs(follower.poll().unwrap()));
+    }
+
+    #[test]
+    fn test_reads_in_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("in.csv");
+        append(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\n",
+        );
+        let mut follower = Follower::open(&path).unwrap();
+        follower.chunk = 24;
+        assert!(follower.poll().unwrap().is_empty());
+        assert!(follower.behind());
+        assert_eq!(vec![(1, 1)], txs(follower.poll().unwrap()));
+        assert!(follower.behind());
+        assert_eq!(vec![(2, 2)], txs(follower.poll().unwrap()));
+        assert!(!follower.behind());
+    }
+}
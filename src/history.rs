@@ -0,0 +1,256 @@
+use crate::audit::{AuditRecord, AuditSink};
+use crate::parse::{Row, parse_timestamp};
+use crate::types::{Account, TransactionType};
+use anyhow::{Result, anyhow};
+use async_stream::stream;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio_stream::{Stream, StreamExt};
+
+/// Point of the input up to which transactions are taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cutoff {
+    /// Up to and including this row
+    Row(u64),
+    /// Up to and including the deposit or withdrawal with this id
+    Tx(u32),
+    /// Up to the first row timestamped after these unix seconds, rows are
+    /// expected in chronological order
+    Time(i64),
+}
+
+impl Cutoff {
+    /// Whether the row comes after the cutoff, a transaction cutoff is only
+    /// passed once the row following the transaction is reached.
+    fn passed_by(&self, row: &Row) -> bool {
+        match self {
+            Cutoff::Row(n) => row.row > *n,
+            Cutoff::Time(t) => row.timestamp.is_some_and(|ts| ts > *t),
+            Cutoff::Tx(_) => false,
+        }
+    }
+
+    fn is_tx(&self, row: &Row) -> bool {
+        match (self, &row.tx) {
+            (Cutoff::Tx(id), Ok(t)) => {
+                t.tx == *id
+                    && matches!(
+                        t.tx_type,
+                        TransactionType::Deposit | TransactionType::Withdrawal
+                    )
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cutoff {
+    type Err = anyhow::Error;
+
+    /// `row:<n>`, `tx:<id>` or `time:<timestamp>`, a bare value is a
+    /// timestamp.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid cutoff: {}", s);
+        match s.split_once(':') {
+            Some(("row", n)) => n.parse().map(Cutoff::Row).map_err(|_| invalid()),
+            Some(("tx", id)) => id.parse().map(Cutoff::Tx).map_err(|_| invalid()),
+            Some(("time", ts)) => parse_timestamp(ts).map(Cutoff::Time),
+            _ => parse_timestamp(s).map(Cutoff::Time).map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Rows up to and including the cutoff. `reached` is set once the cutoff is
+/// met and left unset if the input ends first, e.g. if the transaction of a
+/// [`Cutoff::Tx`] is not in it.
+pub fn until<S: Stream<Item = Row>>(
+    rows: S,
+    cutoff: Cutoff,
+    reached: Arc<AtomicBool>,
+) -> impl Stream<Item = Row> {
+    stream! {
+        tokio::pin!(rows);
+        while let Some(row) = rows.next().await {
+            if cutoff.passed_by(&row) {
+                reached.store(true, Ordering::Relaxed);
+                break;
+            }
+            let last = cutoff.is_tx(&row);
+            yield row;
+            if last {
+                reached.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+}
+
+/// Retains the state of every account after each change so that balances
+/// can be queried as of any point of the input.
+#[derive(Default)]
+pub struct History {
+    /// Snapshots of each account, ordered by row
+    snapshots: BTreeMap<u16, Vec<(u64, Account)>>,
+    /// Row of the deposit or withdrawal of each transaction id
+    tx_rows: HashMap<u32, u64>,
+    /// Timestamped rows, in input order
+    times: Vec<(i64, u64)>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Last row included by the cutoff, None for an unknown transaction.
+    pub fn row_of(&self, cutoff: Cutoff) -> Option<u64> {
+        match cutoff {
+            Cutoff::Row(n) => Some(n),
+            Cutoff::Tx(id) => self.tx_rows.get(&id).copied(),
+            Cutoff::Time(t) => {
+                let i = self.times.partition_point(|(ts, _)| *ts <= t);
+                Some(self.times.get(i).map_or(u64::MAX, |(_, row)| row - 1))
+            }
+        }
+    }
+
+    /// Account of a client as of the cutoff, None if it did not exist yet.
+    pub fn balance_at(&self, client: u16, cutoff: Cutoff) -> Option<&Account> {
+        let row = self.row_of(cutoff)?;
+        let snapshots = self.snapshots.get(&client)?;
+        let i = snapshots.partition_point(|(r, _)| *r <= row);
+        i.checked_sub(1).map(|i| &snapshots[i].1)
+    }
+
+    /// Every account as of the cutoff, ordered by client id.
+    pub fn accounts_at(&self, cutoff: Cutoff) -> Vec<&Account> {
+        self.snapshots
+            .keys()
+            .filter_map(|client| self.balance_at(*client, cutoff))
+            .collect()
+    }
+}
+
+impl AuditSink for History {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        if let Some(ts) = rec.timestamp {
+            self.times.push((ts, rec.row));
+        }
+        let Some(t) = &rec.tx else {
+            return Ok(());
+        };
+        if matches!(
+            t.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            self.tx_rows.entry(t.tx).or_insert(rec.row);
+        }
+        if let Some(after) = &rec.after
+            && rec.before.as_ref() != Some(after)
+        {
+            self.snapshots
+                .entry(t.client)
+                .or_default()
+                .push((rec.row, after.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_rows;
+    use crate::process::process_audited;
+    use crate::stores::{MemActStore, MemTxStore};
+
+    const DATA: &str = "\
+type,client,tx,amount,timestamp
+deposit,1,1,1.0,2024-05-01T10:00:00Z
+deposit,2,2,2.0,2024-05-01T12:00:00Z
+withdrawal,1,3,0.5,2024-05-01T23:00:00Z
+dispute,2,2,,2024-05-02T01:00:00Z
+deposit,1,4,3.0,2024-05-02T02:00:00Z";
+
+    async fn history(cutoff: Cutoff) -> History {
+        let mut act_store = MemActStore::new();
+        let mut tx_store = MemTxStore::new();
+        let mut history = History::new();
+        let rows = until(parse_rows(DATA.as_bytes()), cutoff, Default::default());
+        tokio::pin!(rows);
+        while let Some(row) = rows.next().await {
+            let t = row.tx.unwrap();
+            let rec = process_audited(row.row, t, &mut act_store, &mut tx_store)
+                .with_timestamp(row.timestamp);
+            history.record(&rec).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn test_parse_cutoff() {
+        assert_eq!(Cutoff::Row(10), "row:10".parse().unwrap());
+        assert_eq!(Cutoff::Tx(7), "tx:7".parse().unwrap());
+        assert_eq!(Cutoff::Time(1714607940), "time:1714607940".parse().unwrap());
+        assert_eq!(
+            Cutoff::Time(1714607940),
+            "2024-05-01T23:59:00Z".parse().unwrap()
+        );
+        assert!("row:a".parse::<Cutoff>().is_err());
+        assert!("yesterday".parse::<Cutoff>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_until() {
+        let rows = |cutoff| async move {
+            let reached = Arc::new(AtomicBool::new(false));
+            let rows = until(parse_rows(DATA.as_bytes()), cutoff, reached.clone())
+                .map(|row| row.row)
+                .collect::<Vec<_>>()
+                .await;
+            (rows, reached.load(Ordering::Relaxed))
+        };
+        assert_eq!((vec![1, 2], true), rows(Cutoff::Row(2)).await);
+        assert_eq!((vec![1, 2, 3], true), rows(Cutoff::Tx(3)).await);
+        // The dispute references tx 2 but is not the transaction itself.
+        assert_eq!((vec![1, 2], true), rows(Cutoff::Tx(2)).await);
+        assert_eq!(
+            (vec![1, 2, 3], true),
+            rows("2024-05-01T23:59:00Z".parse().unwrap()).await
+        );
+        assert_eq!((vec![1, 2, 3, 4, 5], false), rows(Cutoff::Tx(99)).await);
+    }
+
+    #[tokio::test]
+    async fn test_balance_at() {
+        let history = history(Cutoff::Row(u64::MAX)).await;
+        let total = |client, cutoff| history.balance_at(client, cutoff).map(|a| a.total());
+        assert_eq!(Some(10000), total(1, Cutoff::Row(2)));
+        assert_eq!(None, total(2, Cutoff::Row(1)));
+        assert_eq!(Some(5000), total(1, Cutoff::Tx(3)));
+        assert_eq!(Some(5000), total(1, Cutoff::Row(4)));
+        assert_eq!(Some(35000), total(1, Cutoff::Tx(4)));
+        assert_eq!(None, total(1, Cutoff::Tx(99)));
+
+        let eod = "2024-05-01T23:59:00Z".parse().unwrap();
+        assert_eq!(Some(5000), total(1, eod));
+        assert_eq!(0, history.balance_at(2, eod).unwrap().held());
+        assert_eq!(20000, history.balance_at(2, Cutoff::Row(4)).unwrap().held());
+        assert_eq!(None, total(1, Cutoff::Time(0)));
+        assert_eq!(2, history.accounts_at(eod).len());
+    }
+
+    #[tokio::test]
+    async fn test_history_matches_cutoff_processing() {
+        let full = history(Cutoff::Row(u64::MAX)).await;
+        for row in 0..=5 {
+            let partial = history(Cutoff::Row(row)).await;
+            assert_eq!(
+                partial.accounts_at(Cutoff::Row(u64::MAX)),
+                full.accounts_at(Cutoff::Row(row))
+            );
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::audit::{AuditRecord, AuditSink};
+use crate::stores::ActStore;
+use crate::types::amount::format_amount;
+use crate::types::{Account, TransactionType, TxKind};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// State of an account derived from its state before its first accepted row
+/// and the accepted rows alone.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Expected {
+    /// Total before the first accepted row
+    opening: i128,
+    deposits: i128,
+    withdrawals: i128,
+    chargebacks: i128,
+    /// Sum of the open disputes
+    held: i128,
+    locked: bool,
+}
+
+impl Expected {
+    fn total(&self) -> i128 {
+        self.opening + self.deposits - self.withdrawals - self.chargebacks
+    }
+}
+
+impl From<Option<&Account>> for Expected {
+    fn from(act: Option<&Account>) -> Self {
+        act.map_or_else(Default::default, |act| Expected {
+            opening: act.total().into(),
+            held: act.held().into(),
+            locked: act.is_locked(),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Total differs from the opening total + deposits - withdrawals -
+    /// chargebacks
+    Total {
+        client: u16,
+        expected: i128,
+        actual: i64,
+    },
+    /// Held differs from the sum of open disputes
+    Held {
+        client: u16,
+        expected: i128,
+        actual: u64,
+    },
+    /// Locked differs from whether the client was locked or had a chargeback
+    Locked {
+        client: u16,
+        expected: bool,
+        actual: bool,
+    },
+    /// The client has accepted transactions but no account
+    MissingAccount { client: u16 },
+}
+
+impl Violation {
+    pub fn client(&self) -> u16 {
+        match self {
+            Violation::Total { client, .. }
+            | Violation::Held { client, .. }
+            | Violation::Locked { client, .. }
+            | Violation::MissingAccount { client } => *client,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Total {
+                client,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {}: total is {}, opening total + deposits - withdrawals - chargebacks is {}",
+                client,
+                format_amount(*actual),
+                format_amount(*expected)
+            ),
+            Violation::Held {
+                client,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {}: held is {}, open disputes sum to {}",
+                client,
+                format_amount(*actual),
+                format_amount(*expected)
+            ),
+            Violation::Locked {
+                client,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {}: locked is {}, expected {}",
+                client, actual, expected
+            ),
+            Violation::MissingAccount { client } => {
+                write!(f, "client {}: account is missing", client)
+            }
+        }
+    }
+}
+
+/// Tracks deposits, withdrawals, disputes and chargebacks of the accepted
+/// rows independently of [`crate::types::Account`] and checks that an
+/// account store agrees with them.
+///
+/// Whether a row is accepted is taken from the audit record, only the
+/// resulting balances are verified. Each client starts from its account
+/// before its first accepted row, so that a store holding earlier runs can
+/// be checked, and only clients with accepted rows are checked.
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    expected: BTreeMap<u16, Expected>,
+}
+
+impl InvariantChecker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Every violated invariant, ordered by client id.
+    pub fn check(&self, store: &dyn ActStore) -> Vec<Violation> {
+        let none = Expected::default();
+        let mut violations = Vec::new();
+        for (client, expected) in self.expected.iter() {
+            let client = *client;
+            let Some(act) = store.get_account(client) else {
+                if *expected != none {
+                    violations.push(Violation::MissingAccount { client });
+                }
+                continue;
+            };
+            if i128::from(act.total()) != expected.total() {
+                violations.push(Violation::Total {
+                    client,
+                    expected: expected.total(),
+                    actual: act.total(),
+                });
+            }
+            if i128::from(act.held()) != expected.held {
+                violations.push(Violation::Held {
+                    client,
+                    expected: expected.held,
+                    actual: act.held(),
+                });
+            }
+            if act.is_locked() != expected.locked {
+                violations.push(Violation::Locked {
+                    client,
+                    expected: expected.locked,
+                    actual: act.is_locked(),
+                });
+            }
+        }
+        violations
+    }
+}
+
+impl AuditSink for InvariantChecker {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        let Some(t) = &rec.tx else {
+            return Ok(());
+        };
+        if !rec.applied() {
+            return Ok(());
+        }
+        let expected = self
+            .expected
+            .entry(t.client)
+            .or_insert_with(|| Expected::from(rec.before.as_ref()));
+        // Disputes, resolves and chargebacks apply to the referenced
+        // transaction, which an accepted row always has.
+        let (amount, kind) = rec
+            .referenced
+            .map_or((0, TxKind::Deposit), |r| (i128::from(r.amount), r.kind));
+        match (t.tx_type, kind) {
+            (TransactionType::Deposit, _) => expected.deposits += i128::from(t.amount),
+            (TransactionType::Withdrawal, _) => expected.withdrawals += i128::from(t.amount),
+            (TransactionType::Dispute, TxKind::Deposit) => expected.held += amount,
+            (TransactionType::Resolve, TxKind::Deposit) => expected.held -= amount,
+            (TransactionType::Chargeback, TxKind::Deposit) => {
+                expected.held -= amount;
+                expected.chargebacks += amount;
+                expected.locked = true;
+            }
+            // A disputed withdrawal is held until resolved, or reversed by
+            // the chargeback.
+            (TransactionType::Dispute, TxKind::Withdrawal) => {
+                expected.held += amount;
+                expected.withdrawals -= amount;
+            }
+            (TransactionType::Resolve, TxKind::Withdrawal) => {
+                expected.held -= amount;
+                expected.withdrawals += amount;
+            }
+            (TransactionType::Chargeback, TxKind::Withdrawal) => {
+                expected.held -= amount;
+                expected.locked = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Fixture, TxRow};
+    use crate::process::Policy;
+    use crate::stores::MemActStore;
+
+    /// Applies the rows, checking the invariants after each.
+    fn run(mut fixture: Fixture, txs: &[TxRow]) -> (MemActStore, InvariantChecker) {
+        let mut checker = InvariantChecker::new();
+        for row in txs {
+            let rec = fixture.apply(*row);
+            checker.record(&rec).unwrap();
+            assert_eq!(Vec::<Violation>::new(), checker.check(&fixture.act_store));
+        }
+        (fixture.act_store, checker)
+    }
+
+    #[test]
+    fn test_processing_keeps_invariants() {
+        run(
+            Fixture::new(),
+            &[
+                (TransactionType::Deposit, 1, 1, 10000),
+                (TransactionType::Deposit, 1, 2, 5000),
+                (TransactionType::Dispute, 1, 1, 0),
+                (TransactionType::Withdrawal, 1, 3, 3000),
+                (TransactionType::Withdrawal, 1, 4, 3000),
+                (TransactionType::Deposit, 2, 5, 20000),
+                (TransactionType::Dispute, 2, 5, 0),
+                (TransactionType::Dispute, 1, 2, 0),
+                (TransactionType::Chargeback, 1, 2, 0),
+                (TransactionType::Resolve, 2, 5, 0),
+                (TransactionType::Withdrawal, 2, 6, 25000),
+                (TransactionType::Dispute, 3, 7, 0),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_disputes_keep_invariants() {
+        let policy = Policy {
+            dispute_withdrawals: true,
+            ..Default::default()
+        };
+        let (act_store, _) = run(
+            Fixture::with_policy(policy),
+            &[
+                (TransactionType::Deposit, 1, 1, 30000),
+                (TransactionType::Withdrawal, 1, 2, 10000),
+                (TransactionType::Withdrawal, 1, 3, 5000),
+                (TransactionType::Dispute, 1, 2, 0),
+                (TransactionType::Dispute, 1, 3, 0),
+                (TransactionType::Resolve, 1, 2, 0),
+                (TransactionType::Chargeback, 1, 3, 0),
+            ],
+        );
+        // Every row is applied, the chargeback reverses the second withdrawal.
+        assert_eq!(20000, act_store.get_account(1).unwrap().total());
+    }
+
+    #[test]
+    fn test_starts_from_stored_accounts() {
+        let mut locked = Account::with_balance(2, 5000);
+        locked.lock();
+        let mut fixture = Fixture::with_accounts([Account::with_balance(1, 20000), locked]);
+        let mut checker = InvariantChecker::new();
+        for rec in fixture.apply_all(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Withdrawal, 1, 2, 25000),
+            (TransactionType::Dispute, 1, 1, 0),
+        ]) {
+            assert!(rec.applied());
+            checker.record(&rec).unwrap();
+        }
+        // Client 2 has no rows and is not checked.
+        assert_eq!(Vec::<Violation>::new(), checker.check(&fixture.act_store));
+        assert_eq!(10000, fixture.act_store.get_account(1).unwrap().held());
+    }
+
+    #[test]
+    fn test_detects_violations() {
+        let (act_store, checker) = run(
+            Fixture::new(),
+            &[
+                (TransactionType::Deposit, 1, 1, 10000),
+                (TransactionType::Dispute, 1, 1, 0),
+                (TransactionType::Deposit, 2, 2, 10000),
+                (TransactionType::Deposit, 3, 3, 10000),
+            ],
+        );
+        let mut accounts: Vec<Account> = vec![
+            Account::with_balance(1, 10000),
+            Account::with_balance(2, 10000),
+        ];
+        accounts[1].lock();
+        let store: MemActStore = accounts.into_iter().collect();
+        assert_eq!(
+            vec![
+                Violation::Held {
+                    client: 1,
+                    expected: 10000,
+                    actual: 0
+                },
+                Violation::Locked {
+                    client: 2,
+                    expected: false,
+                    actual: true
+                },
+                Violation::MissingAccount { client: 3 },
+            ],
+            checker.check(&store)
+        );
+        assert!(checker.check(&act_store).is_empty());
+        assert_eq!(
+            "client 1: held is 0.0000, open disputes sum to 1.0000",
+            checker.check(&store)[0].to_string()
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use crate::audit::{AuditRecord, AuditSink};
+use crate::output::{OutputFormat, RecordWriter};
+use crate::types::amount::format_amount;
+use crate::types::{TransactionType, TxKind};
+use anyhow::Result;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::Write;
+
+/// Accounts of the general ledger.
+///
+/// Client funds are liabilities, a negative client balance is a receivable.
+/// Settlement is the external counterpart of deposits, withdrawals and
+/// chargebacks. Whatever a client owes after a chargeback is expensed as a
+/// chargeback loss against an allowance for losses. Balances a client had
+/// before the first row seen of it are opened against opening balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LedgerAccount {
+    ClientAvailable(u16),
+    ClientHeld(u16),
+    Settlement,
+    ChargebackLosses,
+    LossAllowance,
+    OpeningBalances,
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::ClientAvailable(c) => write!(f, "client:{}:available", c),
+            LedgerAccount::ClientHeld(c) => write!(f, "client:{}:held", c),
+            LedgerAccount::Settlement => write!(f, "settlement"),
+            LedgerAccount::ChargebackLosses => write!(f, "chargeback_losses"),
+            LedgerAccount::LossAllowance => write!(f, "loss_allowance"),
+            LedgerAccount::OpeningBalances => write!(f, "opening_balances"),
+        }
+    }
+}
+
+impl Serialize for LedgerAccount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// One side of an entry, positive amounts are debits and negative amounts
+/// credits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub amount: i128,
+}
+
+/// Balanced postings of an input row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub row: u64,
+    pub tx: u32,
+    pub tx_type: TransactionType,
+    pub postings: Vec<Posting>,
+}
+
+impl Entry {
+    /// Entry of an applied row, None if the row did not move funds.
+    ///
+    /// Postings follow from the transaction and, for disputes, resolves and
+    /// chargebacks, from the transaction they reference. Only the chargeback
+    /// losses depend on the account, through its total before the row.
+    pub fn from_record(rec: &AuditRecord) -> Option<Entry> {
+        let t = rec.tx.as_ref()?;
+        if !rec.applied() {
+            return None;
+        }
+        let available = LedgerAccount::ClientAvailable(t.client);
+        let held = LedgerAccount::ClientHeld(t.client);
+        let settlement = LedgerAccount::Settlement;
+        let referenced = rec.referenced.as_ref();
+        let amount = i128::from(referenced.map_or(t.amount, |r| r.amount));
+        // Debited account, credited account and change of the client total.
+        let (debit, credit, change) = match (t.tx_type, referenced.map(|r| r.kind)) {
+            (TransactionType::Deposit, _) => (settlement, available, amount),
+            (TransactionType::Withdrawal, _) => (available, settlement, -amount),
+            (TransactionType::Dispute, Some(TxKind::Deposit)) => (available, held, 0),
+            (TransactionType::Resolve, Some(TxKind::Deposit)) => (held, available, 0),
+            (TransactionType::Chargeback, Some(TxKind::Deposit)) => (held, settlement, -amount),
+            // A disputed withdrawal is credited back as held funds.
+            (TransactionType::Dispute, Some(TxKind::Withdrawal)) => (settlement, held, amount),
+            (TransactionType::Resolve, Some(TxKind::Withdrawal)) => (held, settlement, -amount),
+            (TransactionType::Chargeback, Some(TxKind::Withdrawal)) => (held, available, 0),
+            (_, None) => return None,
+        };
+        let mut postings = vec![
+            Posting {
+                account: debit,
+                amount,
+            },
+            Posting {
+                account: credit,
+                amount: -amount,
+            },
+        ];
+        let exposure = |total: i128| total.min(0).abs();
+        let total = i128::from(rec.before.as_ref().map_or(0, |act| act.total()));
+        let loss = exposure(total + change) - exposure(total);
+        if loss != 0 {
+            postings.push(Posting {
+                account: LedgerAccount::ChargebackLosses,
+                amount: loss,
+            });
+            postings.push(Posting {
+                account: LedgerAccount::LossAllowance,
+                amount: -loss,
+            });
+        }
+        postings.retain(|p| p.amount != 0);
+        postings.sort_by_key(|p| p.account);
+        if postings.is_empty() {
+            return None;
+        }
+        Some(Entry {
+            row: rec.row,
+            tx: t.tx,
+            tx_type: t.tx_type,
+            postings,
+        })
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.postings.iter().map(|p| p.amount).sum::<i128>() == 0
+    }
+}
+
+/// Flat form of a posting.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct JournalLine {
+    row: u64,
+    tx: u32,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    account: LedgerAccount,
+    debit: Option<String>,
+    credit: Option<String>,
+}
+
+/// Debit and credit columns of a signed amount.
+fn sides(amount: i128) -> (Option<String>, Option<String>) {
+    match amount {
+        a if a > 0 => (Some(format_amount(a)), None),
+        a if a < 0 => (None, Some(format_amount(-a))),
+        _ => (None, None),
+    }
+}
+
+/// Writes the postings of every row as CSV, a JSON array or JSON lines.
+pub struct JournalWriter<W: Write>(RecordWriter<W>);
+
+impl<W: Write> JournalWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        JournalWriter(RecordWriter::new(writer, format))
+    }
+
+    pub fn finish(self) -> Result<W> {
+        self.0.finish()
+    }
+}
+
+impl<W: Write> AuditSink for JournalWriter<W> {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        let Some(entry) = Entry::from_record(rec) else {
+            return Ok(());
+        };
+        for posting in entry.postings {
+            let (debit, credit) = sides(posting.amount);
+            self.0.write(&JournalLine {
+                row: entry.row,
+                tx: entry.tx,
+                tx_type: entry.tx_type,
+                account: posting.account,
+                debit,
+                credit,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Client balance of the ledger differing from the account store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub row: u64,
+    pub account: LedgerAccount,
+    /// Funds owed to the client according to the ledger
+    pub ledger: i128,
+    /// Funds owed to the client according to the account store
+    pub store: i128,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {}: {} is {} in the ledger, {} in the account store",
+            self.row,
+            self.account,
+            format_amount(self.ledger),
+            format_amount(self.store)
+        )
+    }
+}
+
+/// Balance of every ledger account, debits positive.
+///
+/// As a sink, it also checks after every row that the client balances of the
+/// ledger agree with the account of the record.
+#[derive(Debug, Default)]
+pub struct TrialBalance {
+    balances: BTreeMap<LedgerAccount, i128>,
+    /// Clients whose opening balances are posted
+    opened: HashSet<u16>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl TrialBalance {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn post(&mut self, entry: &Entry) {
+        for posting in entry.postings.iter() {
+            *self.balances.entry(posting.account).or_default() += posting.amount;
+        }
+    }
+
+    pub fn balance(&self, account: LedgerAccount) -> i128 {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    pub fn debits(&self) -> i128 {
+        self.balances.values().filter(|b| **b > 0).sum()
+    }
+
+    pub fn credits(&self) -> i128 {
+        -self.balances.values().filter(|b| **b < 0).sum::<i128>()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.debits() == self.credits()
+    }
+
+    /// Rows after which a client balance differed from the account store.
+    pub fn mismatches(&self) -> &[Mismatch] {
+        &self.mismatches
+    }
+
+    /// Posts the balances the client had before its first row.
+    fn open(&mut self, rec: &AuditRecord, client: u16) {
+        if !self.opened.insert(client) {
+            return;
+        }
+        let Some(act) = rec.before.as_ref() else {
+            return;
+        };
+        let available = act.available_exact();
+        let held = i128::from(act.held());
+        for (account, amount) in [
+            (LedgerAccount::ClientAvailable(client), -available),
+            (LedgerAccount::ClientHeld(client), -held),
+            (LedgerAccount::OpeningBalances, available + held),
+        ] {
+            *self.balances.entry(account).or_default() += amount;
+        }
+    }
+
+    /// Compares the client balances with the account after the row.
+    fn reconcile(&mut self, rec: &AuditRecord, client: u16) {
+        let Some(act) = rec.after.as_ref() else {
+            return;
+        };
+        for (account, store) in [
+            (
+                LedgerAccount::ClientAvailable(client),
+                act.available_exact(),
+            ),
+            (LedgerAccount::ClientHeld(client), i128::from(act.held())),
+        ] {
+            let ledger = -self.balance(account);
+            if ledger != store {
+                self.mismatches.push(Mismatch {
+                    row: rec.row,
+                    account,
+                    ledger,
+                    store,
+                });
+            }
+        }
+    }
+
+    /// Writes every account with a balance and the totals as a text table.
+    pub fn write_text<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(w, "{:<24}  {:>20}  {:>20}", "account", "debit", "credit")?;
+        for (account, balance) in self.balances.iter().filter(|(_, b)| **b != 0) {
+            let (debit, credit) = sides(*balance);
+            writeln!(
+                w,
+                "{:<24}  {:>20}  {:>20}",
+                account.to_string(),
+                debit.unwrap_or_default(),
+                credit.unwrap_or_default()
+            )?;
+        }
+        writeln!(
+            w,
+            "{:<24}  {:>20}  {:>20}",
+            "total",
+            format_amount(self.debits()),
+            format_amount(self.credits())
+        )?;
+        Ok(())
+    }
+}
+
+impl AuditSink for TrialBalance {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        let Some(t) = rec.tx.as_ref().filter(|_| rec.applied()) else {
+            return Ok(());
+        };
+        self.open(rec, t.client);
+        if let Some(entry) = Entry::from_record(rec) {
+            self.post(&entry);
+        }
+        self.reconcile(rec, t.client);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Fixture, records};
+    use crate::process::Policy;
+    use crate::types::Account;
+
+    fn posting(account: LedgerAccount, amount: i128) -> Posting {
+        Posting { account, amount }
+    }
+
+    #[test]
+    fn test_entries() {
+        let records = records(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Withdrawal, 1, 2, 4000),
+            (TransactionType::Dispute, 1, 1, 0),
+            (TransactionType::Chargeback, 1, 1, 0),
+            (TransactionType::Withdrawal, 1, 3, 1),
+        ]);
+        let entries: Vec<Option<Entry>> = records.iter().map(Entry::from_record).collect();
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientAvailable(1), -10000),
+                posting(LedgerAccount::Settlement, 10000),
+            ],
+            entries[0].as_ref().unwrap().postings
+        );
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientAvailable(1), 4000),
+                posting(LedgerAccount::Settlement, -4000),
+            ],
+            entries[1].as_ref().unwrap().postings
+        );
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientAvailable(1), 10000),
+                posting(LedgerAccount::ClientHeld(1), -10000),
+            ],
+            entries[2].as_ref().unwrap().postings
+        );
+        // The 0.4 already withdrawn is lost.
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientHeld(1), 10000),
+                posting(LedgerAccount::Settlement, -10000),
+                posting(LedgerAccount::ChargebackLosses, 4000),
+                posting(LedgerAccount::LossAllowance, -4000),
+            ],
+            entries[3].as_ref().unwrap().postings
+        );
+        // Rejected rows post nothing.
+        assert_eq!(None, entries[4]);
+        assert!(entries.iter().flatten().all(Entry::is_balanced));
+    }
+
+    #[test]
+    fn test_trial_balance() {
+        let mut trial = TrialBalance::new();
+        for rec in records(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Deposit, 2, 2, 20000),
+            (TransactionType::Withdrawal, 1, 3, 4000),
+            (TransactionType::Dispute, 1, 1, 0),
+            (TransactionType::Chargeback, 1, 1, 0),
+            (TransactionType::Dispute, 2, 2, 0),
+            (TransactionType::Resolve, 2, 2, 0),
+        ]) {
+            trial.record(&rec).unwrap();
+        }
+        assert!(trial.is_balanced());
+        assert!(trial.mismatches().is_empty());
+        assert_eq!(4000, trial.balance(LedgerAccount::ClientAvailable(1)));
+        assert_eq!(0, trial.balance(LedgerAccount::ClientHeld(1)));
+        assert_eq!(-20000, trial.balance(LedgerAccount::ClientAvailable(2)));
+        assert_eq!(16000, trial.balance(LedgerAccount::Settlement));
+        assert_eq!(4000, trial.balance(LedgerAccount::ChargebackLosses));
+        assert_eq!(-4000, trial.balance(LedgerAccount::LossAllowance));
+
+        let mut out = Vec::new();
+        trial.write_text(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let last = out.lines().last().unwrap();
+        assert_eq!(
+            vec!["total", "2.4000", "2.4000"],
+            last.split_whitespace().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_trial_balance_checks_store() {
+        let mut records = records(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Dispute, 1, 1, 0),
+        ]);
+        // The store lost the held funds of the dispute.
+        records[1].after = Some(Account::with_balance(1, 0));
+        let mut trial = TrialBalance::new();
+        for rec in records {
+            trial.record(&rec).unwrap();
+        }
+        assert!(trial.is_balanced());
+        assert_eq!(
+            [Mismatch {
+                row: 2,
+                account: LedgerAccount::ClientHeld(1),
+                ledger: 10000,
+                store: 0,
+            }],
+            trial.mismatches()
+        );
+        assert_eq!(
+            "row 2: client:1:held is 1.0000 in the ledger, 0.0000 in the account store",
+            trial.mismatches()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_trial_balance_opens_stored_balances() {
+        let mut fixture = Fixture::with_accounts([Account::with_balance(1, 30000)]);
+        let mut trial = TrialBalance::new();
+        let rec = fixture.apply((TransactionType::Withdrawal, 1, 1, 10000));
+        trial.record(&rec).unwrap();
+        assert!(trial.is_balanced());
+        assert!(trial.mismatches().is_empty());
+        assert_eq!(-20000, trial.balance(LedgerAccount::ClientAvailable(1)));
+        assert_eq!(30000, trial.balance(LedgerAccount::OpeningBalances));
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_entries() {
+        let mut fixture = Fixture::with_policy(Policy {
+            dispute_withdrawals: true,
+            ..Default::default()
+        });
+        let mut trial = TrialBalance::new();
+        let mut entries = Vec::new();
+        for rec in fixture.apply_all(&[
+            (TransactionType::Deposit, 1, 1, 30000),
+            (TransactionType::Withdrawal, 1, 2, 10000),
+            (TransactionType::Withdrawal, 1, 3, 5000),
+            (TransactionType::Dispute, 1, 2, 0),
+            (TransactionType::Dispute, 1, 3, 0),
+            (TransactionType::Resolve, 1, 2, 0),
+            (TransactionType::Chargeback, 1, 3, 0),
+        ]) {
+            trial.record(&rec).unwrap();
+            entries.push(Entry::from_record(&rec).unwrap());
+        }
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientHeld(1), -10000),
+                posting(LedgerAccount::Settlement, 10000),
+            ],
+            entries[3].postings
+        );
+        assert_eq!(
+            vec![
+                posting(LedgerAccount::ClientAvailable(1), -5000),
+                posting(LedgerAccount::ClientHeld(1), 5000),
+            ],
+            entries[6].postings
+        );
+        assert!(trial.is_balanced());
+        assert!(trial.mismatches().is_empty());
+        assert_eq!(-20000, trial.balance(LedgerAccount::ClientAvailable(1)));
+        assert_eq!(20000, trial.balance(LedgerAccount::Settlement));
+    }
+
+    #[test]
+    fn test_journal_csv() {
+        let mut writer = JournalWriter::new(Vec::new(), OutputFormat::Csv);
+        for rec in records(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Dispute, 1, 1, 0),
+        ]) {
+            writer.record(&rec).unwrap();
+        }
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            "row,tx,type,account,debit,credit\n\
+             1,1,deposit,client:1:available,,1.0000\n\
+             1,1,deposit,settlement,1.0000,\n\
+             2,1,dispute,client:1:available,1.0000,\n\
+             2,1,dispute,client:1:held,,1.0000\n",
+            out
+        );
+    }
+}
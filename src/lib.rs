@@ -1,4 +1,22 @@
+pub mod audit;
+pub mod config;
+pub mod engine;
+pub mod explain;
+pub mod ffi;
+#[cfg(test)]
+mod fixtures;
+pub mod follow;
+pub mod history;
+pub mod invariants;
+pub mod journal;
+pub mod output;
+pub mod parallel;
 pub mod parse;
 pub mod process;
+pub mod reconcile;
+pub mod server;
+pub mod snapshot;
 pub mod stores;
+pub mod summary;
 pub mod types;
+pub mod watch;
@@ -0,0 +1,159 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    #[serde(alias = "ndjson")]
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            _ => bail!("Unknown output format: {}", s),
+        }
+    }
+}
+
+/// Writes serializable records in the chosen format.
+pub struct RecordWriter<W: Write> {
+    inner: Inner<W>,
+}
+
+enum Inner<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json { writer: W, written: usize },
+    Jsonl(W),
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        let inner = match format {
+            OutputFormat::Csv => {
+                Inner::Csv(Box::new(csv::WriterBuilder::new().from_writer(writer)))
+            }
+            OutputFormat::Json => Inner::Json { writer, written: 0 },
+            OutputFormat::Jsonl => Inner::Jsonl(writer),
+        };
+        RecordWriter { inner }
+    }
+
+    pub fn write<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        match &mut self.inner {
+            Inner::Csv(writer) => writer.serialize(record)?,
+            Inner::Json { writer, written } => {
+                writer.write_all(if *written == 0 { b"[\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut *writer, record)?;
+                *written += 1;
+            }
+            Inner::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completes the output and returns the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        match self.inner {
+            Inner::Csv(writer) => Ok(writer.into_inner().map_err(|e| e.into_error())?),
+            Inner::Json {
+                mut writer,
+                written,
+            } => {
+                writer.write_all(if written == 0 { b"[]\n" } else { b"\n]\n" })?;
+                writer.flush()?;
+                Ok(writer)
+            }
+            Inner::Jsonl(mut writer) => {
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Account;
+    use crate::types::account::AccountSer;
+
+    fn write_all(format: OutputFormat, accounts: &[Account]) -> String {
+        let mut writer = RecordWriter::new(Vec::new(), format);
+        for act in accounts {
+            writer.write(act).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn accounts() -> Vec<Account> {
+        let mut act = Account::with_balance(1, 15000);
+        act.hold(500).unwrap();
+        vec![act, Account::with_balance(2, -10500)]
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            "\
+client,available,held,total,locked
+1,1.4500,0.0500,1.5000,false
+2,-1.0500,0.0000,-1.0500,false
+",
+            write_all(OutputFormat::Csv, &accounts())
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let out = write_all(OutputFormat::Json, &accounts());
+        let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {"client": 1, "available": "1.4500", "held": "0.0500", "total": "1.5000", "locked": false},
+                {"client": 2, "available": "-1.0500", "held": "0.0000", "total": "-1.0500", "locked": false},
+            ]),
+            parsed
+        );
+        assert_eq!("[]\n", write_all(OutputFormat::Json, &[]));
+    }
+
+    #[test]
+    fn test_jsonl_with_disputes() {
+        let mut writer = RecordWriter::new(Vec::new(), OutputFormat::Jsonl);
+        for act in accounts() {
+            writer
+                .write(&AccountSer::from(&act).with_disputes(act.id() as usize))
+                .unwrap();
+        }
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            "\
+{\"client\":1,\"available\":\"1.4500\",\"held\":\"0.0500\",\"total\":\"1.5000\",\"locked\":false,\"disputes\":1}
+{\"client\":2,\"available\":\"-1.0500\",\"held\":\"0.0000\",\"total\":\"-1.0500\",\"locked\":false,\"disputes\":2}
+",
+            out
+        );
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(OutputFormat::Jsonl, "ndjson".parse().unwrap());
+        "xml".parse::<OutputFormat>().expect_err("Unknown format");
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    process::{Policy, process_with},
+    stores::{ActStore, MemActStore, TxStore},
+    types::Transaction,
+};
+use anyhow::{Result, anyhow, bail};
+use log::warn;
+use std::thread::{self, JoinHandle};
+use tokio::sync::mpsc::{Sender, channel};
+use tokio_stream::{Stream, StreamExt};
+
+/// Transactions buffered per shard before senders have to wait.
+const SHARD_QUEUE: usize = 4096;
+
+/// Processes transactions on one thread per shard. Clients are assigned to
+/// shards by id and each shard owns the account and transaction store of
+/// its clients, so transactions of a client are applied in input order.
+///
+/// Transactions can only reference transactions of the same shard, a
+/// dispute for a transaction of another client is rejected as not found
+/// instead of as a client mismatch. Transaction ids reused by different
+/// clients no longer replace each other.
+pub struct ShardedProcessor<S> {
+    senders: Vec<Sender<Transaction>>,
+    workers: Vec<JoinHandle<(S, Box<dyn TxStore + Send>)>>,
+}
+
+impl<S> ShardedProcessor<S>
+where
+    S: ActStore + Send + 'static,
+{
+    /// Starts `shards` workers, creating the stores of each shard with the
+    /// given functions which are called with the shard index.
+    pub fn new<FA, FT>(shards: usize, act_store: FA, tx_store: FT) -> Result<Self>
+    where
+        FA: Fn(usize) -> Result<S>,
+        FT: Fn(usize) -> Result<Box<dyn TxStore + Send>>,
+    {
+        Self::with_policy(shards, act_store, tx_store, Policy::default())
+    }
+
+    /// Same as [`ShardedProcessor::new`], processing under `policy`.
+    pub fn with_policy<FA, FT>(
+        shards: usize,
+        act_store: FA,
+        tx_store: FT,
+        policy: Policy,
+    ) -> Result<Self>
+    where
+        FA: Fn(usize) -> Result<S>,
+        FT: Fn(usize) -> Result<Box<dyn TxStore + Send>>,
+    {
+        Self::with_stores(
+            shards,
+            |shard| Ok((act_store(shard)?, tx_store(shard)?)),
+            policy,
+        )
+    }
+
+    /// Same as [`ShardedProcessor::with_policy`], opening the account and
+    /// transaction stores of a shard together, e.g. on one SQLite connection.
+    pub fn with_stores<F>(shards: usize, stores: F, policy: Policy) -> Result<Self>
+    where
+        F: Fn(usize) -> Result<(S, Box<dyn TxStore + Send>)>,
+    {
+        if shards == 0 {
+            bail!("At least one shard is required");
+        }
+        let mut senders = Vec::with_capacity(shards);
+        let mut workers = Vec::with_capacity(shards);
+        for shard in 0..shards {
+            let (mut acts, mut txs) = stores(shard)?;
+            let (sender, mut receiver) = channel::<Transaction>(SHARD_QUEUE);
+            let worker = thread::Builder::new()
+                .name(format!("act-shard-{}", shard))
+                .spawn(move || {
+                    while let Some(t) = receiver.blocking_recv() {
+                        if let Err(e) = process_with(t.clone(), &mut acts, txs.as_mut(), &policy) {
+                            warn!("Invalid transaction: {:?} {}", t, e);
+                        }
+                    }
+                    (acts, txs)
+                })?;
+            senders.push(sender);
+            workers.push(worker);
+        }
+        Ok(ShardedProcessor { senders, workers })
+    }
+
+    pub fn shards(&self) -> usize {
+        self.senders.len()
+    }
+
+    pub fn shard_of(&self, client: u16) -> usize {
+        client as usize % self.shards()
+    }
+
+    /// Queues a transaction, waiting if its shard is behind.
+    pub async fn send(&self, t: Transaction) -> Result<()> {
+        self.senders[self.shard_of(t.client)]
+            .send(t)
+            .await
+            .map_err(|_| anyhow!("Shard worker stopped"))
+    }
+
+    /// Queues every transaction of the stream.
+    pub async fn send_all<T: Stream<Item = Transaction>>(&self, s: T) -> Result<()> {
+        tokio::pin!(s);
+        while let Some(t) = s.next().await {
+            self.send(t).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for every queued transaction to be processed and merges the
+    /// accounts of all shards. Transaction stores are returned per shard.
+    pub fn finish(self) -> Result<(MemActStore, Vec<Box<dyn TxStore + Send>>)> {
+        let shards = self.shards();
+        drop(self.senders);
+        let mut accounts = Vec::new();
+        let mut tx_stores = Vec::with_capacity(shards);
+        for (shard, worker) in self.workers.into_iter().enumerate() {
+            let (store, txs) = worker
+                .join()
+                .map_err(|_| anyhow!("Shard {} worker panicked", shard))?;
+            // Stores shared between shards may know about other clients.
+            accounts.extend(
+                store
+                    .accounts()
+                    .filter(|act| act.id() as usize % shards == shard)
+                    .cloned(),
+            );
+            tx_stores.push(txs);
+        }
+        Ok((accounts.into_iter().collect(), tx_stores))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::process;
+    use crate::stores::MemTxStore;
+    use crate::types::{Account, TransactionType};
+
+    fn processor(shards: usize) -> ShardedProcessor<MemActStore> {
+        ShardedProcessor::new(
+            shards,
+            |_| Ok(MemActStore::new()),
+            |_| Ok(Box::new(MemTxStore::new())),
+        )
+        .unwrap()
+    }
+
+    fn tx(tx_type: TransactionType, client: u16, tx: u32, amount: u64) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_sequential_processing() {
+        let mut txs = Vec::new();
+        for client in 0..50u16 {
+            let base = client as u32 * 10;
+            txs.push(tx(TransactionType::Deposit, client, base, 10000));
+            txs.push(tx(TransactionType::Deposit, client, base + 1, 5000));
+            txs.push(tx(TransactionType::Withdrawal, client, base + 2, 12000));
+            txs.push(tx(TransactionType::Dispute, client, base + 1, 0));
+            if client % 2 == 0 {
+                txs.push(tx(TransactionType::Chargeback, client, base + 1, 0));
+            }
+            txs.push(tx(TransactionType::Withdrawal, client, base + 3, 100));
+        }
+
+        let mut act_store = MemActStore::new();
+        let mut tx_store = MemTxStore::new();
+        for t in txs.iter().cloned() {
+            let _ = process(t, &mut act_store, &mut tx_store);
+        }
+        let expected: Vec<Account> = act_store.accounts().cloned().collect();
+
+        let processor = processor(4);
+        processor.send_all(tokio_stream::iter(txs)).await.unwrap();
+        let (merged, _) = processor.finish().unwrap();
+        assert_eq!(expected, merged.accounts().cloned().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn preserves_client_order() {
+        let processor = processor(3);
+        processor
+            .send(tx(TransactionType::Withdrawal, 1, 1, 100))
+            .await
+            .unwrap();
+        processor
+            .send(tx(TransactionType::Deposit, 1, 2, 100))
+            .await
+            .unwrap();
+        processor
+            .send(tx(TransactionType::Withdrawal, 1, 3, 50))
+            .await
+            .unwrap();
+        let (accounts, tx_stores) = processor.finish().unwrap();
+        assert_eq!(3, tx_stores.len());
+        assert_eq!(1, accounts.count());
+        assert_eq!(50, accounts.get_account(1).unwrap().total());
+    }
+
+    #[test]
+    fn requires_a_shard() {
+        assert!(
+            ShardedProcessor::<MemActStore>::new(
+                0,
+                |_| Ok(MemActStore::new()),
+                |_| Ok(Box::new(MemTxStore::new())),
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,17 +1,30 @@
 use crate::types::Transaction;
+use anyhow::{Result, anyhow};
 use async_stream::stream;
+use chrono::DateTime;
+use csv::StringRecord;
 use std::io::Read;
 use tokio_stream::Stream;
 
+/// An input row, invalid rows are kept with the reason they were rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// 1-based, the header is not counted
+    pub row: u64,
+    pub tx: Result<Transaction, String>,
+    /// Unix seconds of the optional timestamp column
+    pub timestamp: Option<i64>,
+}
+
 pub fn parse<R: Read>(input: R) -> impl Stream<Item = Transaction> {
-    let mut reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .has_headers(true)
-        .from_reader(input);
+    transactions(parse_rows(input))
+}
 
+/// Transactions of the valid rows, invalid rows are reported on stderr.
+pub fn transactions<S: Stream<Item = Row>>(rows: S) -> impl Stream<Item = Transaction> {
     stream! {
-        for tx in reader.deserialize() {
-            match tx {
+        for await row in rows {
+            match row.tx {
                 Ok(tx) => yield tx,
                 Err(e) => eprintln!("Error reading CSV: {}", e),
             }
@@ -19,6 +32,126 @@ pub fn parse<R: Read>(input: R) -> impl Stream<Item = Transaction> {
     }
 }
 
+/// Same as [`parse`], also yielding rows that could not be parsed.
+pub fn parse_rows<R: Read>(input: R) -> impl Stream<Item = Row> {
+    let mut reader = reader_builder().has_headers(true).from_reader(input);
+
+    stream! {
+        let parser = match reader.headers() {
+            Ok(headers) => RecordParser::new(headers.clone()),
+            Err(e) => {
+                yield Row { row: 1, tx: Err(e.to_string()), timestamp: None };
+                return;
+            }
+        };
+        for (i, record) in reader.records().enumerate() {
+            let row = i as u64 + 1;
+            yield match record {
+                Ok(record) => parser.parse(row, &record),
+                Err(e) => Row { row, tx: Err(e.to_string()), timestamp: None },
+            };
+        }
+    }
+}
+
+fn reader_builder() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.trim(csv::Trim::All);
+    builder
+}
+
+/// Parses records one at a time with the rules of [`parse`], for inputs
+/// that are not read through a single CSV reader.
+#[derive(Debug, Clone)]
+pub struct RecordParser {
+    headers: StringRecord,
+    /// Column of the optional timestamp
+    ts_col: Option<usize>,
+}
+
+impl RecordParser {
+    pub fn new(mut headers: StringRecord) -> Self {
+        headers.trim();
+        let ts_col = headers.iter().position(|h| h == "timestamp");
+        RecordParser { headers, ts_col }
+    }
+
+    /// Parser of the rows following a header line.
+    pub fn from_header_line(line: &str) -> Result<Self> {
+        Ok(RecordParser::new(read_line(line)?.unwrap_or_default()))
+    }
+
+    pub fn has_column(&self, name: &str) -> bool {
+        self.headers.iter().any(|h| h == name)
+    }
+
+    pub fn parse(&self, row: u64, record: &StringRecord) -> Row {
+        let timestamp = match self.ts_col.and_then(|col| record.get(col)) {
+            None | Some("") => Ok(None),
+            Some(ts) => parse_timestamp(ts).map(Some),
+        };
+        match timestamp {
+            Ok(timestamp) => Row {
+                row,
+                tx: record
+                    .deserialize(Some(&self.headers))
+                    .map_err(|e: csv::Error| e.to_string()),
+                timestamp,
+            },
+            Err(e) => Row {
+                row,
+                tx: Err(e.to_string()),
+                timestamp: None,
+            },
+        }
+    }
+
+    /// Parses a single line of CSV, None for a blank line.
+    pub fn parse_line(&self, row: u64, line: &str) -> Option<Row> {
+        match read_line(line) {
+            Ok(Some(record)) => Some(self.parse(row, &record)),
+            Ok(None) => None,
+            Err(e) => Some(Row {
+                row,
+                tx: Err(e.to_string()),
+                timestamp: None,
+            }),
+        }
+    }
+}
+
+fn read_line(line: &str) -> Result<Option<StringRecord>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    // Building a reader is costly, only quoted fields need one.
+    if !line.contains('"') {
+        let mut record: StringRecord = line.split(',').collect();
+        record.trim();
+        return Ok(Some(record));
+    }
+    let mut reader = reader_builder()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(line.as_bytes());
+    let mut record = StringRecord::new();
+    match reader.read_record(&mut record)? {
+        true => Ok(Some(record)),
+        false => Ok(None),
+    }
+}
+
+/// Parses unix seconds or an RFC 3339 date, e.g. 2024-05-01T23:59:00Z.
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    if let Ok(secs) = s.parse::<i64>() {
+        return Ok(secs);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.timestamp())
+        .map_err(|_| anyhow!("invalid timestamp: {}", s))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,4 +355,73 @@ dispute,1,2,";
 
         assert_eq!(expected, txs);
     }
+
+    #[tokio::test]
+    async fn invalid_rows_are_numbered() {
+        let data = "\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.00001
+withdrawal,1,3,0.5";
+        let rows = parse_rows(data.as_bytes()).collect::<Vec<Row>>().await;
+        assert_eq!(3, rows.len());
+        assert_eq!(
+            vec![1, 2, 3],
+            rows.iter().map(|row| row.row).collect::<Vec<_>>()
+        );
+        assert!(rows[0].tx.is_ok());
+        assert!(
+            rows[1]
+                .tx
+                .as_ref()
+                .is_err_and(|e| e.contains("decimal precision"))
+        );
+        assert_eq!(5000, rows[2].tx.as_ref().unwrap().amount);
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_parsed() {
+        let data = "\
+type,client,tx,amount,timestamp
+deposit,1,1,1.0,1714607940
+deposit,1,2,1.0,2024-05-01T23:59:00Z
+deposit,1,3,1.0,
+deposit,1,4,1.0,yesterday";
+        let rows = parse_rows(data.as_bytes()).collect::<Vec<Row>>().await;
+        assert_eq!(
+            vec![Some(1714607940), Some(1714607940), None, None],
+            rows.iter().map(|row| row.timestamp).collect::<Vec<_>>()
+        );
+        assert!(rows[2].tx.is_ok());
+        assert!(
+            rows[3]
+                .tx
+                .as_ref()
+                .is_err_and(|e| e.contains("invalid timestamp"))
+        );
+    }
+
+    #[test]
+    fn lines_are_parsed_like_files() {
+        let parser = RecordParser::from_header_line("type, client, tx, amount").unwrap();
+        let row = parser.parse_line(1, "deposit,  1, 2, 1.5").unwrap();
+        assert_eq!(
+            Ok(Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 2,
+                amount: 15000,
+            }),
+            row.tx
+        );
+        assert_eq!(None, parser.parse_line(2, ""));
+        assert!(
+            parser
+                .parse_line(2, "deposit,1,2,1.00001")
+                .unwrap()
+                .tx
+                .is_err_and(|e| e.contains("decimal precision"))
+        );
+        assert!(parser.parse_line(3, "deposit,1").unwrap().tx.is_err());
+    }
 }
@@ -1,95 +1,386 @@
 use crate::{
-    stores::ActStore,
-    types::{Transaction, TransactionType},
+    audit::{AuditRecord, Outcome},
+    stores::{ActStore, AsyncActStore, TxStore},
+    types::{Account, Transaction, TransactionType, TxKind, TxRecord, TxState, amount::PRECISION},
 };
 use anyhow::{Result, anyhow, bail};
-use std::collections::HashMap;
+use serde::Deserialize;
 
-#[derive(Debug, Clone)]
-pub struct InternalTransaction {
-    tx: Transaction,
-    disputed: bool,
+/// Looks up the transaction referenced by a dispute, resolve or chargeback
+/// and checks that it belongs to the same client. Withdrawals are only kept
+/// to reject duplicate ids unless the policy lets them be disputed.
+fn referenced(
+    t: &Transaction,
+    tx_store: &dyn TxStore,
+    policy: &Policy,
+    action: &str,
+) -> Result<TxRecord> {
+    let tx = tx_store
+        .get(t.tx)?
+        .filter(|tx| tx.kind == TxKind::Deposit || policy.dispute_withdrawals)
+        .ok_or(anyhow!("{}: Transaction not found in store", action))?;
+    if t.client != tx.client {
+        bail!(
+            "{}: client mismatch. Transaction client: {}, {} client: {}",
+            action,
+            tx.client,
+            action,
+            t.client
+        )
+    }
+    Ok(tx)
+}
+
+/// Rules applied on top of the transaction semantics, the default is the
+/// behaviour of [`process`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Reject every transaction of a locked account
+    pub freeze_locked: bool,
+    /// Let a chargeback take the total of the account below zero
+    pub negative_chargeback: bool,
+    /// Let a dispute take the available funds of the account below zero
+    pub negative_available: bool,
+    /// Let withdrawals be disputed. A disputed withdrawal
+    /// is credited back as held funds, a resolve withdraws it again and a
+    /// chargeback releases the funds and locks the account.
+    pub dispute_withdrawals: bool,
+    /// Decimal places accepted in amounts, at most [`PRECISION`]
+    pub decimals: u32,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            freeze_locked: false,
+            negative_chargeback: true,
+            negative_available: true,
+            dispute_withdrawals: false,
+            decimals: PRECISION,
+        }
+    }
+}
+
+impl Policy {
+    /// Fails if the amount has more than [`Policy::decimals`] decimal places.
+    pub fn check_decimals(&self, t: &Transaction) -> Result<()> {
+        if !t
+            .amount
+            .is_multiple_of(10u64.pow(PRECISION.saturating_sub(self.decimals)))
+        {
+            bail!(
+                "{:?}: More than {} decimal places",
+                t.tx_type,
+                self.decimals
+            )
+        }
+        Ok(())
+    }
 }
 
 /// Processes a transaction by updating the account store and transaction store.
 pub fn process(
     t: Transaction,
     act_store: &mut dyn ActStore,
-    tx_store: &mut HashMap<u32, InternalTransaction>,
+    tx_store: &mut dyn TxStore,
 ) -> Result<i64> {
-    match t.tx_type {
-        TransactionType::Deposit => act_store.deposit(t.client, t.amount).inspect(|_| {
-            tx_store.insert(t.tx, InternalTransaction{ tx: t, disputed: false});
-        }),
-        TransactionType::Withdrawal => act_store.withdraw(t.client, t.amount),
-        TransactionType::Dispute => tx_store
-            .get_mut(&t.tx)
-            .ok_or(anyhow!("Dispute: Transaction not found in store"))
-            .and_then(|tx| {
-                if t.client != tx.tx.client {
-                    bail!(
-                        "Dispute: client mismatch. Transaction client: {}, Dispute client: {}",
-                        tx.tx.client,
-                        t.client
-                    )
+    process_with(t, act_store, tx_store, &Policy::default())
+}
+
+/// Same as [`process`] under the given policy.
+pub fn process_with(
+    t: Transaction,
+    act_store: &mut dyn ActStore,
+    tx_store: &mut dyn TxStore,
+    policy: &Policy,
+) -> Result<i64> {
+    act_store.begin()?;
+    let res = apply_plan(&t, act_store, tx_store, policy)
+        .and_then(|available| act_store.commit().map(|()| available));
+    if res.is_err() {
+        act_store.rollback();
+    }
+    res
+}
+
+/// Applies the transaction within the store transaction opened by
+/// [`process_with`].
+fn apply_plan(
+    t: &Transaction,
+    act_store: &mut dyn ActStore,
+    tx_store: &mut dyn TxStore,
+    policy: &Policy,
+) -> Result<i64> {
+    let plan = Plan::new(t, act_store.get_account(t.client), tx_store, policy)?;
+    let mut available = 0;
+    for op in plan.act_ops {
+        available = match op {
+            ActOp::Deposit(amnt) => act_store.deposit(t.client, amnt)?,
+            ActOp::Withdraw(amnt) => act_store.withdraw(t.client, amnt)?,
+            ActOp::WithdrawUnchecked(amnt) => act_store.withdraw_unchecked(t.client, amnt)?,
+            ActOp::Hold(amnt) => act_store.hold(t.client, amnt)?,
+            ActOp::Unhold(amnt) => act_store.unhold(t.client, amnt)?,
+            ActOp::Lock => {
+                act_store.lock_account(t.client);
+                available
+            }
+        };
+    }
+    plan.tx_op.apply(tx_store)?;
+    Ok(available)
+}
+
+/// Account store operation of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ActOp {
+    Deposit(u64),
+    Withdraw(u64),
+    WithdrawUnchecked(u64),
+    Hold(u64),
+    Unhold(u64),
+    Lock,
+}
+
+/// Transaction store update once the account store operations succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxOp {
+    Insert(u32, TxRecord),
+    SetState(u32, TxState),
+}
+
+impl TxOp {
+    fn apply(self, tx_store: &mut dyn TxStore) -> Result<()> {
+        match self {
+            TxOp::Insert(tx, record) => tx_store.insert(tx, record),
+            TxOp::SetState(tx, state) => tx_store.set_state(tx, state),
+        }
+    }
+}
+
+/// What a transaction does to the stores, decided from the account of the
+/// client and the transaction store alone so that [`process_with`] and
+/// [`process_async_with`] share the rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Plan {
+    /// Applied in order, the available funds returned by the last one that
+    /// is not a lock are the result of the transaction
+    act_ops: Vec<ActOp>,
+    tx_op: TxOp,
+}
+
+impl Plan {
+    /// Plan of the transaction, checked against a copy of the account so
+    /// that no operation fails once the stores are being updated.
+    fn new(
+        t: &Transaction,
+        account: Option<&Account>,
+        tx_store: &dyn TxStore,
+        policy: &Policy,
+    ) -> Result<Plan> {
+        let plan = Self::build(t, account, tx_store, policy)?;
+        let mut act = account.cloned().unwrap_or_else(|| Account::new(t.client));
+        for op in &plan.act_ops {
+            match *op {
+                ActOp::Deposit(amnt) => act.deposit(amnt)?,
+                ActOp::Withdraw(amnt) => act.withdraw(amnt)?,
+                ActOp::WithdrawUnchecked(amnt) => act.withdraw_allow_negative(amnt)?,
+                ActOp::Hold(amnt) => act.hold(amnt)?,
+                ActOp::Unhold(amnt) => act.unhold(amnt)?,
+                ActOp::Lock => {
+                    act.lock();
+                    0
                 }
-                if tx.disputed {
-                    bail!("Dispute: Transaction already disputed")
+            };
+        }
+        Ok(plan)
+    }
+
+    fn build(
+        t: &Transaction,
+        account: Option<&Account>,
+        tx_store: &dyn TxStore,
+        policy: &Policy,
+    ) -> Result<Plan> {
+        if policy.freeze_locked && account.is_some_and(|act| act.is_locked()) {
+            bail!("{:?}: Account locked", t.tx_type)
+        }
+        policy.check_decimals(t)?;
+        if matches!(
+            t.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) && tx_store.get(t.tx)?.is_some()
+        {
+            bail!("{:?}: Duplicate transaction id {}", t.tx_type, t.tx)
+        }
+        let plan = |act_ops: Vec<ActOp>, tx_op| Ok(Plan { act_ops, tx_op });
+        match t.tx_type {
+            TransactionType::Deposit => plan(
+                vec![ActOp::Deposit(t.amount)],
+                TxOp::Insert(t.tx, TxRecord::from(t)),
+            ),
+            TransactionType::Withdrawal => plan(
+                vec![ActOp::Withdraw(t.amount)],
+                TxOp::Insert(t.tx, TxRecord::from(t)),
+            ),
+            TransactionType::Dispute => {
+                let tx = referenced(t, tx_store, policy, "Dispute")?;
+                match tx.state {
+                    TxState::Settled => {}
+                    TxState::Disputed => bail!("Dispute: Transaction already disputed"),
+                    TxState::ChargedBack => bail!("Dispute: Transaction already charged back"),
                 }
-                tx.disputed = true;
-                act_store.hold(t.client, tx.tx.amount)
-            }),
-        TransactionType::Resolve => tx_store
-            .get_mut(&t.tx)
-            .ok_or(anyhow!("Resolve: Transaction not found in store"))
-            .and_then(|tx| {
-                if t.client != tx.tx.client {
-                    bail!(
-                        "Resolve: client mismatch. Transaction client: {}, Resolve client: {}",
-                        tx.tx.client,
-                        t.client
-                    )
+                let disputed = TxOp::SetState(t.tx, TxState::Disputed);
+                match tx.kind {
+                    TxKind::Deposit => {
+                        if !policy.negative_available
+                            && account.is_some_and(|act| {
+                                act.available()
+                                    .map_or(true, |a| a.checked_sub_unsigned(tx.amount) < Some(0))
+                            })
+                        {
+                            bail!("Dispute: Would result in negative available funds")
+                        }
+                        plan(vec![ActOp::Hold(tx.amount)], disputed)
+                    }
+                    TxKind::Withdrawal => plan(
+                        vec![ActOp::Deposit(tx.amount), ActOp::Hold(tx.amount)],
+                        disputed,
+                    ),
                 }
-                if !tx.disputed {
+            }
+            TransactionType::Resolve => {
+                let tx = referenced(t, tx_store, policy, "Resolve")?;
+                if tx.state != TxState::Disputed {
                     bail!("Resolve: Transaction not disputed")
                 }
-                tx.disputed = false;
-                act_store.unhold(t.client, tx.tx.amount)
-            }),
-        TransactionType::Chargeback => {
-            tx_store
-                .get(&t.tx)
-                .ok_or(anyhow!("Chargeback: Transaction not found in store"))
-                .and_then(|tx| {
-                if t.client != tx.tx.client {
-                        bail!(
-                            "Chargeback: client mismatch. Transaction client: {}, Chargeback client: {}",
-                            tx.tx.client,
-                            t.client
-                        )
+                let mut act_ops = vec![ActOp::Unhold(tx.amount)];
+                if tx.kind == TxKind::Withdrawal {
+                    act_ops.push(ActOp::WithdrawUnchecked(tx.amount));
                 }
-                if !tx.disputed {
+                plan(act_ops, TxOp::SetState(t.tx, TxState::Settled))
+            }
+            TransactionType::Chargeback => {
+                let tx = referenced(t, tx_store, policy, "Chargeback")?;
+                if tx.state != TxState::Disputed {
                     bail!("Chargeback: Transaction not disputed")
                 }
-                act_store.lock_account(t.client);
-                act_store.unhold(t.client, tx.tx.amount)?;
-                act_store.withdraw_unchecked(t.client, tx.tx.amount)
-            })
+                let charged_back = TxOp::SetState(t.tx, TxState::ChargedBack);
+                if tx.kind == TxKind::Withdrawal {
+                    // The withdrawal is reversed, the funds credited back by
+                    // the dispute become available.
+                    return plan(vec![ActOp::Lock, ActOp::Unhold(tx.amount)], charged_back);
+                }
+                if !policy.negative_chargeback
+                    && account
+                        .is_some_and(|act| act.total().checked_sub_unsigned(tx.amount) < Some(0))
+                {
+                    bail!("Chargeback: Would result in negative total")
+                }
+                plan(
+                    vec![
+                        ActOp::Lock,
+                        ActOp::Unhold(tx.amount),
+                        ActOp::WithdrawUnchecked(tx.amount),
+                    ],
+                    charged_back,
+                )
+            }
         }
     }
 }
 
+/// Same as [`process`], returning a record of the outcome for audit.
+pub fn process_audited(
+    row: u64,
+    t: Transaction,
+    act_store: &mut dyn ActStore,
+    tx_store: &mut dyn TxStore,
+) -> AuditRecord {
+    process_audited_with(row, t, act_store, tx_store, &Policy::default())
+}
+
+/// Same as [`process_audited`] under the given policy.
+pub fn process_audited_with(
+    row: u64,
+    t: Transaction,
+    act_store: &mut dyn ActStore,
+    tx_store: &mut dyn TxStore,
+    policy: &Policy,
+) -> AuditRecord {
+    let client = t.client;
+    let before = act_store.get_account(client).cloned();
+    let referenced = match t.tx_type {
+        TransactionType::Deposit | TransactionType::Withdrawal => None,
+        _ => tx_store
+            .get(t.tx)
+            .ok()
+            .flatten()
+            .filter(|tx| tx.client == client),
+    };
+    let result = process_with(t.clone(), act_store, tx_store, policy);
+    AuditRecord {
+        row,
+        timestamp: None,
+        tx: Some(t),
+        outcome: match result {
+            Ok(_) => Outcome::Applied,
+            Err(_) => Outcome::Rejected,
+        },
+        reason: result.err().map(|e| e.to_string()),
+        before,
+        after: act_store.get_account(client).cloned(),
+        referenced,
+    }
+}
+
+/// Same as [`process`] for account stores doing I/O.
+pub async fn process_async<A: AsyncActStore>(
+    t: Transaction,
+    act_store: &A,
+    tx_store: &mut dyn TxStore,
+) -> Result<i64> {
+    process_async_with(t, act_store, tx_store, &Policy::default()).await
+}
+
+/// Same as [`process_async`] under the given policy.
+pub async fn process_async_with<A: AsyncActStore>(
+    t: Transaction,
+    act_store: &A,
+    tx_store: &mut dyn TxStore,
+    policy: &Policy,
+) -> Result<i64> {
+    let account = act_store.get_account(t.client).await?;
+    let plan = Plan::new(&t, account.as_ref(), tx_store, policy)?;
+    let mut available = 0;
+    for op in plan.act_ops {
+        available = match op {
+            ActOp::Deposit(amnt) => act_store.deposit(t.client, amnt).await?,
+            ActOp::Withdraw(amnt) => act_store.withdraw(t.client, amnt).await?,
+            ActOp::WithdrawUnchecked(amnt) => act_store.withdraw_unchecked(t.client, amnt).await?,
+            ActOp::Hold(amnt) => act_store.hold(t.client, amnt).await?,
+            ActOp::Unhold(amnt) => act_store.unhold(t.client, amnt).await?,
+            ActOp::Lock => {
+                act_store.lock_account(t.client).await?;
+                available
+            }
+        };
+    }
+    plan.tx_op.apply(tx_store)?;
+    Ok(available)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::stores::MemActStore;
+    use crate::stores::{AsyncAdapter, MemActStore, MemTxStore};
     use crate::types::Transaction;
     use crate::types::TransactionType;
 
     #[test]
     fn valid_tx_and_over_limit_withdraw() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         let txs = vec![
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -140,10 +431,67 @@ mod tests {
         assert!(matches!(act.available(), Ok(20000)));
     }
 
+    #[test]
+    fn duplicate_tx_ids() {
+        let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let tx = |tx_type, client, amount| Transaction {
+            tx_type,
+            amount,
+            client,
+            tx: 1,
+        };
+        process(
+            tx(TransactionType::Deposit, 1, 10000),
+            act_store.as_mut(),
+            &mut tx_store,
+        )
+        .unwrap();
+        for t in [
+            tx(TransactionType::Deposit, 1, 10000),
+            tx(TransactionType::Deposit, 2, 10000),
+            tx(TransactionType::Withdrawal, 1, 5000),
+        ] {
+            process(t, act_store.as_mut(), &mut tx_store).expect_err("Transaction ids are unique");
+        }
+        assert_eq!(10000, act_store.get_account(1).unwrap().total());
+        assert!(act_store.get_account(2).is_none());
+        let record = tx_store.get(1).unwrap().unwrap();
+        assert_eq!(
+            (1, 10000, TxKind::Deposit),
+            (record.client, record.amount, record.kind)
+        );
+    }
+
+    #[test]
+    fn withdrawals_kept_but_not_disputable_by_default() {
+        let mut act_store = MemActStore::new();
+        let mut tx_store = MemTxStore::new();
+        let txs = [
+            (TransactionType::Deposit, 1, 10000, true),
+            (TransactionType::Withdrawal, 2, 4000, true),
+            (TransactionType::Dispute, 2, 0, false),
+            (TransactionType::Withdrawal, 2, 4000, false),
+        ];
+        for (tx_type, tx, amount, applied) in txs {
+            let t = Transaction {
+                tx_type,
+                amount,
+                client: 1,
+                tx,
+            };
+            let res = process(t, &mut act_store, &mut tx_store);
+            assert_eq!(applied, res.is_ok(), "{:?} {}: {:?}", tx_type, tx, res);
+        }
+        let act = act_store.get_account(1).unwrap();
+        assert_eq!((6000, 0), (act.total(), act.held()));
+        assert_eq!(TxKind::Withdrawal, tx_store.get(2).unwrap().unwrap().kind);
+    }
+
     #[test]
     fn held_funds() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         process(
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -201,7 +549,7 @@ mod tests {
     #[test]
     fn dispute_client_mismatch() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         process(
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -231,7 +579,7 @@ mod tests {
     #[test]
     fn chargeback() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         let txs = vec![
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -276,10 +624,145 @@ mod tests {
         assert!(matches!(act.available(), Ok(-10000)));
         assert!(act.is_locked());
     }
+    #[test]
+    fn policy() {
+        let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let policy = Policy {
+            freeze_locked: true,
+            negative_chargeback: false,
+            ..Default::default()
+        };
+        let tx = |tx_type, tx, amount| Transaction {
+            tx_type,
+            amount,
+            client: 1,
+            tx,
+        };
+        for t in [
+            tx(TransactionType::Deposit, 1, 20000),
+            tx(TransactionType::Deposit, 2, 10000),
+            tx(TransactionType::Withdrawal, 3, 15000),
+            tx(TransactionType::Dispute, 1, 0),
+            tx(TransactionType::Dispute, 2, 0),
+        ] {
+            process_with(t, act_store.as_mut(), &mut tx_store, &policy).unwrap();
+        }
+        process_with(
+            tx(TransactionType::Chargeback, 1, 0),
+            act_store.as_mut(),
+            &mut tx_store,
+            &policy,
+        )
+        .expect_err("Chargeback should fail as the total would be negative");
+        process_with(
+            tx(TransactionType::Chargeback, 2, 0),
+            act_store.as_mut(),
+            &mut tx_store,
+            &policy,
+        )
+        .unwrap();
+        let err = process_with(
+            tx(TransactionType::Deposit, 4, 10000),
+            act_store.as_mut(),
+            &mut tx_store,
+            &policy,
+        )
+        .expect_err("Deposit should fail as the account is locked");
+        assert_eq!("Deposit: Account locked", err.to_string());
+        let act = act_store.get_account(1).unwrap();
+        assert_eq!(20000, act.held());
+        assert_eq!(-15000, act.available().unwrap());
+    }
+
+    #[test]
+    fn withdrawal_disputes() {
+        let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let policy = Policy {
+            dispute_withdrawals: true,
+            negative_available: false,
+            decimals: 2,
+            ..Default::default()
+        };
+        let mut apply = |tx_type, tx, amount| {
+            let t = Transaction {
+                tx_type,
+                amount,
+                client: 1,
+                tx,
+            };
+            process_with(t, act_store.as_mut(), &mut tx_store, &policy)
+                .map(|_| act_store.get_account(1).unwrap().clone())
+        };
+        let err = apply(TransactionType::Deposit, 1, 12345).unwrap_err();
+        assert_eq!("Deposit: More than 2 decimal places", err.to_string());
+        apply(TransactionType::Deposit, 1, 20000).unwrap();
+        apply(TransactionType::Withdrawal, 2, 15000).unwrap();
+        let err = apply(TransactionType::Dispute, 1, 0).unwrap_err();
+        assert_eq!(
+            "Dispute: Would result in negative available funds",
+            err.to_string()
+        );
+
+        let act = apply(TransactionType::Dispute, 2, 0).unwrap();
+        assert_eq!((20000, 15000), (act.total(), act.held()));
+        let act = apply(TransactionType::Resolve, 2, 0).unwrap();
+        assert_eq!((5000, 0), (act.total(), act.held()));
+        apply(TransactionType::Dispute, 2, 0).unwrap();
+        let act = apply(TransactionType::Chargeback, 2, 0).unwrap();
+        assert_eq!((20000, 0), (act.total(), act.held()));
+        assert!(act.is_locked());
+    }
+    #[test]
+    fn chargeback_is_final() {
+        let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let txs = vec![
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                amount: 10000,
+                client: 1,
+                tx: 1,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                amount: 0,
+                client: 1,
+                tx: 1,
+            },
+            Transaction {
+                tx_type: TransactionType::Chargeback,
+                amount: 0,
+                client: 1,
+                tx: 1,
+            },
+        ];
+        for tx in txs {
+            process(tx, act_store.as_mut(), &mut tx_store).unwrap();
+        }
+        for tx_type in [TransactionType::Chargeback, TransactionType::Dispute] {
+            process(
+                Transaction {
+                    tx_type,
+                    amount: 0,
+                    client: 1,
+                    tx: 1,
+                },
+                act_store.as_mut(),
+                &mut tx_store,
+            )
+            .expect_err("Charged back transactions cannot be reused");
+        }
+        let act = act_store.get_account(1).unwrap();
+        assert_eq!(0, act.held());
+        assert_eq!(0, act.total());
+    }
+
     #[test]
     fn resolve() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         process(
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -324,7 +807,7 @@ mod tests {
     #[test]
     fn lock_unlock_account() {
         let mut act_store: Box<dyn ActStore> = Box::new(MemActStore::new());
-        let mut tx_store: HashMap<u32, InternalTransaction> = HashMap::new();
+        let mut tx_store = MemTxStore::new();
         process(
             Transaction {
                 tx_type: TransactionType::Deposit,
@@ -343,4 +826,122 @@ mod tests {
         let act = act_store.get_account(1).unwrap();
         assert!(!act.is_locked());
     }
+
+    #[tokio::test]
+    async fn async_chargeback() {
+        let act_store = AsyncAdapter::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let txs = vec![
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                amount: 20000,
+                client: 1,
+                tx: 1,
+            },
+            Transaction {
+                tx_type: TransactionType::Withdrawal,
+                amount: 10000,
+                client: 1,
+                tx: 2,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                amount: 0,
+                client: 1,
+                tx: 1,
+            },
+            Transaction {
+                tx_type: TransactionType::Chargeback,
+                amount: 0,
+                client: 1,
+                tx: 1,
+            },
+        ];
+        for tx in txs {
+            process_async(tx, &act_store, &mut tx_store).await.unwrap();
+        }
+        process_async(
+            Transaction {
+                tx_type: TransactionType::Resolve,
+                amount: 0,
+                client: 1,
+                tx: 1,
+            },
+            &act_store,
+            &mut tx_store,
+        )
+        .await
+        .expect_err("Resolve should fail after chargeback");
+        let act = act_store.get_account(1).await.unwrap().unwrap();
+        assert_eq!(0, act.held());
+        assert_eq!(-10000, act.total());
+        assert!(act.is_locked());
+    }
+
+    #[tokio::test]
+    async fn async_withdrawal_disputes() {
+        let act_store = AsyncAdapter::new(MemActStore::new());
+        let mut tx_store = MemTxStore::new();
+        let policy = Policy {
+            dispute_withdrawals: true,
+            freeze_locked: true,
+            decimals: 2,
+            ..Default::default()
+        };
+        let txs = [
+            (TransactionType::Deposit, 1, 12345, false),
+            (TransactionType::Deposit, 1, 20000, true),
+            (TransactionType::Deposit, 1, 20000, false),
+            (TransactionType::Withdrawal, 2, 15000, true),
+            (TransactionType::Dispute, 2, 0, true),
+            (TransactionType::Chargeback, 2, 0, true),
+            (TransactionType::Deposit, 3, 10000, false),
+        ];
+        for (tx_type, tx, amount, applied) in txs {
+            let t = Transaction {
+                tx_type,
+                amount,
+                client: 1,
+                tx,
+            };
+            let res = process_async_with(t, &act_store, &mut tx_store, &policy).await;
+            assert_eq!(applied, res.is_ok(), "{:?} {}: {:?}", tx_type, tx, res);
+        }
+        let act = act_store.get_account(1).await.unwrap().unwrap();
+        assert_eq!((20000, 0), (act.total(), act.held()));
+        assert!(act.is_locked());
+        assert_eq!(
+            TxState::ChargedBack,
+            tx_store.get(2).unwrap().unwrap().state
+        );
+    }
+
+    #[test]
+    fn withdrawal_dispute_is_atomic() {
+        // Available funds already overflow: crediting the withdrawal back
+        // succeeds, holding it fails.
+        let mut act_store = MemActStore::from_iter([Account::from_parts(1, i64::MIN, 10, false)]);
+        let mut tx_store = MemTxStore::new();
+        let record = TxRecord {
+            client: 1,
+            amount: 50,
+            state: TxState::Settled,
+            kind: TxKind::Withdrawal,
+        };
+        tx_store.insert(1, record).unwrap();
+        let before = act_store.get_account(1).unwrap().clone();
+        let t = Transaction {
+            tx_type: TransactionType::Dispute,
+            amount: 0,
+            client: 1,
+            tx: 1,
+        };
+        let policy = Policy {
+            dispute_withdrawals: true,
+            ..Default::default()
+        };
+        process_with(t, &mut act_store, &mut tx_store, &policy).expect_err("Hold overflows");
+        assert_eq!(Some(&before), act_store.get_account(1));
+        assert_eq!(Some(record), tx_store.get(1).unwrap());
+    }
 }
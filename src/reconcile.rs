@@ -0,0 +1,236 @@
+use crate::stores::ActStore;
+use crate::types::Account;
+use crate::types::amount::{format_amount, parse_amount, parse_exact_amount, parse_signed_amount};
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Balances of a client as reported by another system.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub available: i128,
+    pub held: u64,
+    pub total: i64,
+    pub locked: bool,
+}
+
+impl From<&Account> for Balance {
+    fn from(act: &Account) -> Self {
+        Balance {
+            available: act.available_exact(),
+            held: act.held(),
+            total: act.total(),
+            locked: act.is_locked(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BalanceRow {
+    client: u16,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+/// Reads expected balances in the output format of the accounts, extra
+/// columns are ignored.
+pub fn read_expected<R: Read>(input: R) -> Result<BTreeMap<u16, Balance>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input);
+    let mut expected = BTreeMap::new();
+    for (i, row) in reader.deserialize::<BalanceRow>().enumerate() {
+        let row = row?;
+        let invalid = |e: anyhow::Error| anyhow!("Expected balances row {}: {}", i + 1, e);
+        let balance = Balance {
+            available: parse_exact_amount(&row.available).map_err(invalid)?,
+            held: parse_amount(&row.held).map_err(invalid)?,
+            total: parse_signed_amount(&row.total).map_err(invalid)?,
+            locked: row.locked,
+        };
+        if expected.insert(row.client, balance).is_some() {
+            bail!(
+                "Expected balances row {}: client {} is listed twice",
+                i + 1,
+                row.client
+            );
+        }
+    }
+    Ok(expected)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    /// The client is on one side only, `present` or `missing` on each
+    Account,
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+/// A field of a client that differs from the expected balances.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Discrepancy {
+    pub client: u16,
+    pub field: Field,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Differences between the expected balances and the accounts, ordered by
+/// client id. A client missing on either side is a single
+/// [`Field::Account`] discrepancy.
+pub fn reconcile(expected: &BTreeMap<u16, Balance>, store: &dyn ActStore) -> Vec<Discrepancy> {
+    let mut clients: BTreeMap<u16, (Option<Balance>, Option<Balance>)> = expected
+        .iter()
+        .map(|(client, balance)| (*client, (Some(balance.clone()), None)))
+        .collect();
+    for act in store.accounts() {
+        clients.entry(act.id()).or_default().1 = Some(Balance::from(act));
+    }
+
+    let mut discrepancies = Vec::new();
+    for (client, balances) in clients {
+        let presence = |balance: &Option<Balance>| match balance {
+            Some(_) => String::from("present"),
+            None => String::from("missing"),
+        };
+        let (expected, actual) = match balances {
+            (Some(expected), Some(actual)) => (expected, actual),
+            (expected, actual) => {
+                discrepancies.push(Discrepancy {
+                    client,
+                    field: Field::Account,
+                    expected: presence(&expected),
+                    actual: presence(&actual),
+                });
+                continue;
+            }
+        };
+        let mut differs = |field, expected: String, actual: String| {
+            if expected != actual {
+                discrepancies.push(Discrepancy {
+                    client,
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        };
+        differs(
+            Field::Available,
+            format_amount(expected.available),
+            format_amount(actual.available),
+        );
+        differs(
+            Field::Held,
+            format_amount(expected.held),
+            format_amount(actual.held),
+        );
+        differs(
+            Field::Total,
+            format_amount(expected.total),
+            format_amount(actual.total),
+        );
+        differs(
+            Field::Locked,
+            expected.locked.to_string(),
+            actual.locked.to_string(),
+        );
+    }
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemActStore;
+
+    #[test]
+    fn test_read_expected() {
+        let data = "\
+client, available, held, total, locked, disputes
+1, 1.5, 0, 1.5, false, 0
+2, -2.0000, 1.0000, -1.0000, true, 1";
+        let expected = read_expected(data.as_bytes()).unwrap();
+        assert_eq!(
+            Balance {
+                available: -20000,
+                held: 10000,
+                total: -10000,
+                locked: true
+            },
+            expected[&2]
+        );
+        assert_eq!(15000, expected[&1].total);
+
+        // Held funds can take the available amount below i64::MIN.
+        let overflow = "client,available,held,total,locked\n\
+            1,-1844674407370955.1616,1844674407370955.1615,-0.0001,false";
+        assert_eq!(
+            -i128::from(u64::MAX) - 1,
+            read_expected(overflow.as_bytes()).unwrap()[&1].available
+        );
+
+        let duplicate = "client,available,held,total,locked\n1,0,0,0,false\n1,0,0,0,false";
+        assert!(read_expected(duplicate.as_bytes()).is_err());
+        let precision = "client,available,held,total,locked\n1,0.00001,0,0,false";
+        assert!(
+            read_expected(precision.as_bytes())
+                .unwrap_err()
+                .to_string()
+                .contains("row 1")
+        );
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut locked = Account::with_balance(2, 10000);
+        locked.lock();
+        let store: MemActStore = vec![
+            Account::with_balance(1, 10000),
+            locked,
+            Account::with_balance(3, 10000),
+            Account::new(4),
+        ]
+        .into_iter()
+        .collect();
+        let expected = read_expected(
+            "\
+client,available,held,total,locked
+1,1.0,0,1.0,false
+2,1.0,0,1.0,false
+3,0.5,0.5,1.0,false
+5,0,0,0,false"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let discrepancy = |client, field, expected: &str, actual: &str| Discrepancy {
+            client,
+            field,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        };
+        assert_eq!(
+            vec![
+                discrepancy(2, Field::Locked, "false", "true"),
+                discrepancy(3, Field::Available, "0.5000", "1.0000"),
+                discrepancy(3, Field::Held, "0.5000", "0.0000"),
+                discrepancy(4, Field::Account, "missing", "present"),
+                discrepancy(5, Field::Account, "present", "missing"),
+            ],
+            reconcile(&expected, &store)
+        );
+        let actual: BTreeMap<u16, Balance> = store
+            .accounts()
+            .map(|act| (act.id(), Balance::from(act)))
+            .collect();
+        assert!(reconcile(&actual, &store).is_empty());
+    }
+}
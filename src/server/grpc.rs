@@ -0,0 +1,320 @@
+use super::{SharedLedger, with_ledger};
+use crate::audit::AuditRecord;
+use crate::types::amount::{format_amount, parse_amount};
+use crate::types::{Account, Transaction, TransactionType};
+use anyhow::Result;
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Types and service generated from `proto/act.proto`.
+pub mod proto {
+    tonic::include_proto!("act");
+}
+
+use proto::act_server::{Act, ActServer};
+
+impl TryFrom<proto::Transaction> for Transaction {
+    type Error = Status;
+
+    fn try_from(t: proto::Transaction) -> Result<Self, Status> {
+        let tx_type = match proto::TransactionType::try_from(t.r#type) {
+            Ok(proto::TransactionType::Deposit) => TransactionType::Deposit,
+            Ok(proto::TransactionType::Withdrawal) => TransactionType::Withdrawal,
+            Ok(proto::TransactionType::Dispute) => TransactionType::Dispute,
+            Ok(proto::TransactionType::Resolve) => TransactionType::Resolve,
+            Ok(proto::TransactionType::Chargeback) => TransactionType::Chargeback,
+            Err(_) => return Err(Status::invalid_argument("Unknown transaction type")),
+        };
+        Ok(Transaction {
+            tx_type,
+            client: u16::try_from(t.client)
+                .map_err(|_| Status::invalid_argument("Client out of range"))?,
+            tx: t.tx,
+            amount: parse_amount(&t.amount).map_err(|e| Status::invalid_argument(e.to_string()))?,
+        })
+    }
+}
+
+impl From<&Account> for proto::Account {
+    fn from(act: &Account) -> Self {
+        proto::Account {
+            client: act.id().into(),
+            available: format_amount(act.available_exact()),
+            held: format_amount(act.held()),
+            total: format_amount(act.total()),
+            locked: act.is_locked(),
+        }
+    }
+}
+
+impl From<AuditRecord> for proto::TxResult {
+    fn from(rec: AuditRecord) -> Self {
+        proto::TxResult {
+            client: rec.tx.as_ref().map_or(0, |t| t.client.into()),
+            tx: rec.tx.as_ref().map_or(0, |t| t.tx),
+            applied: rec.applied(),
+            reason: rec.reason.unwrap_or_default(),
+            account: rec.after.as_ref().map(proto::Account::from),
+        }
+    }
+}
+
+/// gRPC service applying transactions to a shared ledger.
+pub struct ActService {
+    ledger: SharedLedger,
+}
+
+impl ActService {
+    pub fn new(ledger: SharedLedger) -> Self {
+        ActService { ledger }
+    }
+
+    async fn apply(&self, t: Transaction) -> Result<proto::TxResult, Status> {
+        let rec = with_ledger(&self.ledger, |ledger| ledger.apply(t)).await;
+        rec.and_then(|rec| rec)
+            .map(proto::TxResult::from)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+
+    async fn account(&self, client: u16) -> Result<Option<proto::Account>, Status> {
+        with_ledger(&self.ledger, move |ledger| {
+            ledger
+                .act_store()
+                .get_account(client)
+                .map(proto::Account::from)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+type AccountStream = Pin<Box<dyn Stream<Item = Result<proto::Account, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Act for ActService {
+    async fn submit(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::TxResult>, Status> {
+        let t = Transaction::try_from(request.into_inner())?;
+        Ok(Response::new(self.apply(t).await?))
+    }
+
+    /// Transactions that cannot be converted are rejected without stopping
+    /// the stream.
+    async fn submit_stream(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<proto::TxResults>, Status> {
+        let mut stream = request.into_inner();
+        let mut results = Vec::new();
+        while let Some(t) = stream.message().await? {
+            let (client, tx) = (t.client, t.tx);
+            results.push(match Transaction::try_from(t) {
+                Ok(t) => self.apply(t).await?,
+                Err(status) => proto::TxResult {
+                    client,
+                    tx,
+                    applied: false,
+                    reason: status.message().to_string(),
+                    account: None,
+                },
+            });
+        }
+        Ok(Response::new(proto::TxResults { results }))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::AccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let client = client(request.get_ref())?;
+        self.account(client)
+            .await?
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("Unknown client {}", client)))
+    }
+
+    type WatchAccountStream = AccountStream;
+
+    /// A watcher falling behind gets the latest state of the account instead
+    /// of the updates it missed.
+    async fn watch_account(
+        &self,
+        request: Request<proto::AccountRequest>,
+    ) -> Result<Response<AccountStream>, Status> {
+        let client = client(request.get_ref())?;
+        let (mut updates, current) = with_ledger(&self.ledger, move |ledger| {
+            let current = ledger
+                .act_store()
+                .get_account(client)
+                .map(proto::Account::from);
+            (ledger.subscribe(), current)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        let service = ActService::new(self.ledger.clone());
+        let stream = async_stream::stream! {
+            if let Some(act) = current {
+                yield Ok(act);
+            }
+            loop {
+                match updates.recv().await {
+                    Ok(act) if act.id() == client => yield Ok(proto::Account::from(&act)),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(_)) => match service.account(client).await {
+                        Ok(Some(act)) => yield Ok(act),
+                        Ok(None) => {}
+                        Err(status) => yield Err(status),
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn client(request: &proto::AccountRequest) -> Result<u16, Status> {
+    u16::try_from(request.client).map_err(|_| Status::invalid_argument("Client out of range"))
+}
+
+/// Serves the gRPC API until the listener fails.
+pub async fn serve(listener: TcpListener, ledger: SharedLedger) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(ActServer::new(ActService::new(ledger)))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::Ledger;
+    use crate::stores::{MemActStore, MemTxStore};
+    use proto::act_client::ActClient;
+    use tokio_stream::StreamExt;
+    use tonic::Code;
+    use tonic::transport::Channel;
+
+    async fn start() -> ActClient<Channel> {
+        let ledger = Ledger::new(Box::new(MemActStore::new()), Box::new(MemTxStore::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, ledger.shared()));
+        ActClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    fn tx(
+        tx_type: proto::TransactionType,
+        client: u32,
+        tx: u32,
+        amount: &str,
+    ) -> proto::Transaction {
+        proto::Transaction {
+            r#type: tx_type.into(),
+            client,
+            tx,
+            amount: amount.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_and_get() {
+        let mut client = start().await;
+        let result = client
+            .submit(tx(proto::TransactionType::Deposit, 1, 1, "1.5"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(result.applied);
+        assert_eq!("1.5000", result.account.unwrap().total);
+
+        let results = client
+            .submit_stream(tokio_stream::iter(vec![
+                tx(proto::TransactionType::Withdrawal, 1, 2, "2.0"),
+                tx(proto::TransactionType::Deposit, 1, 3, "0.00001"),
+                tx(proto::TransactionType::Dispute, 1, 1, ""),
+            ]))
+            .await
+            .unwrap()
+            .into_inner()
+            .results;
+        assert_eq!(
+            vec![false, false, true],
+            results.iter().map(|r| r.applied).collect::<Vec<_>>()
+        );
+        assert!(results[1].reason.contains("precision"));
+
+        let account = client
+            .get_account(proto::AccountRequest { client: 1 })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            proto::Account {
+                client: 1,
+                available: String::from("0.0000"),
+                held: String::from("1.5000"),
+                total: String::from("1.5000"),
+                locked: false,
+            },
+            account
+        );
+
+        let status = client
+            .get_account(proto::AccountRequest { client: 2 })
+            .await
+            .unwrap_err();
+        assert_eq!(Code::NotFound, status.code());
+        let status = client
+            .submit(tx(proto::TransactionType::Deposit, 70000, 1, "1"))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+    }
+
+    #[tokio::test]
+    async fn test_watch_account() {
+        let mut client = start().await;
+        client
+            .submit(tx(proto::TransactionType::Deposit, 1, 1, "1.0"))
+            .await
+            .unwrap();
+        let mut watch = client
+            .watch_account(proto::AccountRequest { client: 1 })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("1.0000", watch.next().await.unwrap().unwrap().total);
+
+        client
+            .submit(tx(proto::TransactionType::Deposit, 2, 2, "5.0"))
+            .await
+            .unwrap();
+        client
+            .submit(tx(proto::TransactionType::Withdrawal, 1, 3, "0.25"))
+            .await
+            .unwrap();
+        client
+            .submit(tx(proto::TransactionType::Withdrawal, 1, 4, "5.0"))
+            .await
+            .unwrap();
+        client
+            .submit(tx(proto::TransactionType::Dispute, 1, 1, ""))
+            .await
+            .unwrap();
+        let update = watch.next().await.unwrap().unwrap();
+        assert_eq!(1, update.client);
+        assert_eq!("0.7500", update.total);
+        // The rejected withdrawal did not change the account.
+        assert_eq!("1.0000", watch.next().await.unwrap().unwrap().held);
+    }
+}
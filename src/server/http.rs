@@ -0,0 +1,333 @@
+use super::{SharedLedger, with_ledger, ws};
+use crate::audit::{AuditRecord, Outcome};
+use crate::stores::{AccountFilter, AccountOrder};
+use crate::types::account::AccountSer;
+use crate::types::amount::format_amount;
+use crate::types::{Transaction, TransactionType, TxState};
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+/// Outcome of a submitted transaction.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TxResult {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    outcome: Outcome,
+    reason: Option<String>,
+    /// Account of the client after the transaction
+    account: Option<AccountSer>,
+}
+
+impl TxResult {
+    fn new(t: &Transaction, rec: AuditRecord) -> Self {
+        TxResult {
+            tx_type: t.tx_type,
+            client: t.client,
+            tx: t.tx,
+            outcome: rec.outcome,
+            reason: rec.reason,
+            account: rec.after.as_ref().map(AccountSer::from),
+        }
+    }
+}
+
+/// What is known about a deposit.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TxView {
+    tx: u32,
+    client: u16,
+    amount: String,
+    state: TxState,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AccountsQuery {
+    locked: Option<bool>,
+}
+
+fn error(status: StatusCode, message: impl ToString) -> Response {
+    (status, Json(json!({ "error": message.to_string() }))).into_response()
+}
+
+/// Routes of the HTTP API:
+///
+/// * `POST /transactions`: a transaction or an array of transactions,
+///   amounts are decimal strings, e.g. `{"type": "deposit", "client": 1,
+///   "tx": 1, "amount": "1.5"}`
+/// * `GET /accounts` and `GET /accounts/{client}`, `?locked=true` only lists
+///   locked accounts
+/// * `GET /transactions/{tx}`: dispute state of a deposit
+/// * `GET /ws`: WebSocket streaming account updates, see [`super::ws`]
+pub fn router(ledger: SharedLedger) -> Router {
+    Router::new()
+        .route("/transactions", post(submit))
+        .route("/transactions/{tx}", get(get_transaction))
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/{client}", get(get_account))
+        .route("/ws", get(ws::handler))
+        .with_state(ledger)
+}
+
+/// Serves the API until the listener fails.
+pub async fn serve(listener: TcpListener, ledger: SharedLedger) -> Result<()> {
+    axum::serve(listener, router(ledger)).await?;
+    Ok(())
+}
+
+/// Applies the transactions in order. A single transaction that is rejected
+/// is answered with 422, a batch is always answered with 200 and the result
+/// of each transaction.
+async fn submit(State(ledger): State<SharedLedger>, Json(body): Json<Value>) -> Response {
+    let batch = body.is_array();
+    let txs: serde_json::Result<Vec<Transaction>> = match batch {
+        true => serde_json::from_value(body),
+        false => serde_json::from_value(body).map(|t| vec![t]),
+    };
+    let txs = match txs {
+        Ok(txs) => txs,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+
+    let results = with_ledger(&ledger, |ledger| {
+        txs.into_iter()
+            .map(|t| Ok(TxResult::new(&t, ledger.apply(t.clone())?)))
+            .collect::<Result<Vec<TxResult>>>()
+    })
+    .await;
+    let mut results = match results.and_then(|results| results) {
+        Ok(results) => results,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    match (batch, results.pop()) {
+        (false, Some(result)) if result.outcome == Outcome::Rejected => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(result)).into_response()
+        }
+        (false, Some(result)) => Json(result).into_response(),
+        (_, last) => {
+            results.extend(last);
+            Json(results).into_response()
+        }
+    }
+}
+
+async fn get_account(State(ledger): State<SharedLedger>, Path(client): Path<u16>) -> Response {
+    let act = with_ledger(&ledger, move |ledger| {
+        ledger.act_store().get_account(client).map(AccountSer::from)
+    })
+    .await;
+    match act {
+        Ok(Some(act)) => Json(act).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("Unknown client {}", client)),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn list_accounts(
+    State(ledger): State<SharedLedger>,
+    Query(query): Query<AccountsQuery>,
+) -> Response {
+    let filter = AccountFilter {
+        locked: query.locked,
+        ..Default::default()
+    };
+    let accounts = with_ledger(&ledger, move |ledger| {
+        ledger
+            .act_store()
+            .select(&filter, AccountOrder::Client)
+            .into_iter()
+            .map(AccountSer::from)
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match accounts {
+        Ok(accounts) => Json(accounts).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn get_transaction(State(ledger): State<SharedLedger>, Path(tx): Path<u32>) -> Response {
+    let record = with_ledger(&ledger, move |ledger| ledger.tx_store().get(tx)).await;
+    match record.and_then(|record| record) {
+        Ok(Some(record)) => Json(TxView {
+            tx,
+            client: record.client,
+            amount: format_amount(record.amount),
+            state: record.state,
+        })
+        .into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("Unknown transaction {}", tx)),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::Ledger;
+    use crate::stores::{MemActStore, MemTxStore, SqliteActStore};
+    use crate::types::Transaction;
+    use reqwest::Client;
+
+    async fn start() -> String {
+        start_with(Ledger::new(
+            Box::new(MemActStore::new()),
+            Box::new(MemTxStore::new()),
+        ))
+        .await
+    }
+
+    async fn start_with(ledger: Ledger) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, ledger.shared()));
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_submit_and_query() {
+        let url = start().await;
+        let client = Client::new();
+
+        let res = client
+            .post(format!("{}/transactions", url))
+            .json(&json!({"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let body: Value = res.json().await.unwrap();
+        assert_eq!("applied", body["outcome"]);
+        assert_eq!("1.5000", body["account"]["total"]);
+
+        let res = client
+            .post(format!("{}/transactions", url))
+            .json(&json!([
+                {"type": "deposit", "client": 2, "tx": 2, "amount": "2.0"},
+                {"type": "withdrawal", "client": 2, "tx": 3, "amount": "3.0"},
+                {"type": "dispute", "client": 1, "tx": 1},
+            ]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(3, body.as_array().unwrap().len());
+        assert_eq!("rejected", body[1]["outcome"]);
+        assert_eq!("1.5000", body[2]["account"]["held"]);
+
+        let body: Value = client
+            .get(format!("{}/accounts/1", url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            json!({"client": 1, "available": "0.0000", "held": "1.5000",
+                   "total": "1.5000", "locked": false}),
+            body
+        );
+
+        let body: Value = client
+            .get(format!("{}/accounts", url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let clients: Vec<u64> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|act| act["client"].as_u64().unwrap())
+            .collect();
+        assert_eq!(vec![1, 2], clients);
+
+        let body: Value = client
+            .get(format!("{}/transactions/1", url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            json!({"tx": 1, "client": 1, "amount": "1.5000", "state": "disputed"}),
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let url = start().await;
+        let client = Client::new();
+
+        let res = client
+            .post(format!("{}/transactions", url))
+            .json(&json!({"type": "withdrawal", "client": 1, "tx": 1, "amount": "1.0"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+
+        let res = client
+            .post(format!("{}/transactions", url))
+            .json(&json!({"type": "deposit", "client": 1, "tx": 1, "amount": "1.00001"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+        let body: Value = res.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("precision"));
+
+        for path in ["accounts/7", "transactions/7"] {
+            let res = client
+                .get(format!("{}/{}", url, path))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::NOT_FOUND, res.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_state_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("act.db");
+        let open = || {
+            let act_store = SqliteActStore::open(&path).unwrap();
+            let tx_store = act_store.tx_store();
+            Ledger::new(Box::new(act_store), Box::new(tx_store))
+        };
+        open()
+            .apply(Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: 10000,
+            })
+            .unwrap();
+
+        let url = start_with(open()).await;
+        let res = Client::new()
+            .post(format!("{}/transactions", url))
+            .json(&json!({"type": "dispute", "client": 1, "tx": 1}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let body: Value = res.json().await.unwrap();
+        assert_eq!("1.0000", body["account"]["held"]);
+    }
+}
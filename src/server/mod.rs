@@ -0,0 +1,99 @@
+pub mod grpc;
+pub mod http;
+pub mod tcp;
+pub mod ws;
+
+use crate::audit::{AuditRecord, AuditSink};
+use crate::engine::{Engine, EngineBuilder};
+use crate::stores::{ActStore, TxStore};
+use crate::types::{Account, Transaction};
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Account updates kept for subscribers that are behind.
+const UPDATES: usize = 1024;
+
+/// Engine of a long-lived service, transactions are applied one at a time
+/// in the order they are received.
+pub struct Ledger {
+    engine: Engine<'static>,
+    updates: broadcast::Sender<Account>,
+}
+
+pub type SharedLedger = Arc<Mutex<Ledger>>;
+
+/// Runs `f` on the locked ledger on tokio's blocking thread pool, so that
+/// waiting for the lock or for stores doing I/O, e.g. SQLite, does not
+/// stall the async workers.
+pub async fn with_ledger<T, F>(ledger: &SharedLedger, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Ledger) -> T + Send + 'static,
+{
+    let ledger = ledger.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut ledger = ledger.lock().map_err(|_| anyhow!("Ledger lock poisoned"))?;
+        Ok(f(&mut ledger))
+    })
+    .await?
+}
+
+impl Ledger {
+    pub fn new(act_store: Box<dyn ActStore + Send>, tx_store: Box<dyn TxStore + Send>) -> Self {
+        Engine::builder()
+            .act_store(act_store)
+            .tx_store(tx_store)
+            .into()
+    }
+
+    pub fn shared(self) -> SharedLedger {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Fails only if a sink of the engine fails.
+    pub fn apply(&mut self, t: Transaction) -> Result<AuditRecord> {
+        self.engine.apply(t)
+    }
+
+    /// Accounts changed by the transactions applied from now on, a
+    /// subscriber that falls behind misses the oldest updates.
+    pub fn subscribe(&self) -> broadcast::Receiver<Account> {
+        self.updates.subscribe()
+    }
+
+    pub fn act_store(&self) -> &dyn ActStore {
+        self.engine.act_store()
+    }
+
+    pub fn tx_store(&self) -> &dyn TxStore {
+        self.engine.tx_store()
+    }
+}
+
+/// Builds the engine with a sink publishing the accounts it changes, so that
+/// subscribers see every row the engine applies.
+impl From<EngineBuilder<'static>> for Ledger {
+    fn from(builder: EngineBuilder<'static>) -> Self {
+        let updates = broadcast::channel(UPDATES).0;
+        Ledger {
+            engine: builder.sink(Publisher(updates.clone())).build(),
+            updates,
+        }
+    }
+}
+
+/// Sends the account of every row that changed it.
+struct Publisher(broadcast::Sender<Account>);
+
+impl AuditSink for Publisher {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        if let Some(after) = &rec.after
+            && rec.before.as_ref() != Some(after)
+        {
+            // Nobody may be subscribed.
+            let _ = self.0.send(after.clone());
+        }
+        Ok(())
+    }
+}
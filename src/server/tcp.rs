@@ -0,0 +1,207 @@
+use super::{SharedLedger, with_ledger};
+use crate::audit::{AuditRecord, Outcome};
+use crate::parse::{RecordParser, Row};
+use anyhow::Result;
+use log::{info, warn};
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{OwnedPermit, Receiver, Sender, channel};
+
+/// Rows queued for the processor before connections have to wait.
+const QUEUE: usize = 1024;
+/// Rows of a connection waiting for their acknowledgement before the
+/// connection stops reading.
+const IN_FLIGHT: usize = 256;
+
+/// Acknowledgement of a row of a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub row: u64,
+    pub outcome: Outcome,
+    pub reason: Option<String>,
+}
+
+impl Ack {
+    fn new(row: u64, rec: AuditRecord) -> Self {
+        Ack {
+            row,
+            outcome: rec.outcome,
+            reason: rec.reason,
+        }
+    }
+
+    /// `row,outcome,reason` CSV line.
+    fn line(&self) -> String {
+        let reason = self.reason.as_deref().unwrap_or_default();
+        let reason = match reason.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", reason.replace('"', "\"\"")),
+            false => reason.to_string(),
+        };
+        format!(
+            "{},{},{}\n",
+            self.row,
+            format!("{:?}", self.outcome).to_lowercase(),
+            reason
+        )
+    }
+}
+
+struct Job {
+    row: Row,
+    ack: OwnedPermit<Ack>,
+}
+
+/// Accepts connections sending CSV, a header line then one row per line,
+/// parsed with the rules of [`crate::parse::parse`]. Rows of all
+/// connections are applied by a single task in the order they are queued,
+/// each row is acknowledged to its connection with a `row,outcome,reason`
+/// line. Connections stop being read while the processor is behind.
+pub async fn serve(listener: TcpListener, ledger: SharedLedger) -> Result<()> {
+    let (jobs, receiver) = channel::<Job>(QUEUE);
+    tokio::spawn(process(receiver, ledger));
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let jobs = jobs.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection(stream, addr, jobs).await {
+                warn!("Connection {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn process(mut receiver: Receiver<Job>, ledger: SharedLedger) {
+    while let Some(job) = receiver.recv().await {
+        let rec = match job.row.tx {
+            Ok(t) => match with_ledger(&ledger, |ledger| ledger.apply(t))
+                .await
+                .and_then(|rec| rec)
+            {
+                Ok(rec) => rec,
+                Err(e) => {
+                    warn!("Row {} not recorded: {}", job.row.row, e);
+                    AuditRecord::invalid(job.row.row, e.to_string())
+                }
+            },
+            Err(e) => AuditRecord::invalid(job.row.row, e),
+        };
+        // The connection may be gone, its rows are still applied.
+        job.ack.send(Ack::new(job.row.row, rec));
+    }
+}
+
+async fn connection(stream: TcpStream, addr: SocketAddr, jobs: Sender<Job>) -> Result<()> {
+    info!("Connection from {}", addr);
+    let (reader, writer) = stream.into_split();
+    let (acks, ack_receiver) = channel::<Ack>(IN_FLIGHT);
+    let writing = tokio::spawn(write_acks(ack_receiver, writer));
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut parser = None;
+    let mut row = 0;
+    while let Some(line) = lines.next_line().await? {
+        let Some(parser) = &parser else {
+            parser = Some(RecordParser::from_header_line(&line)?);
+            continue;
+        };
+        let Some(parsed) = parser.parse_line(row + 1, &line) else {
+            continue;
+        };
+        row += 1;
+        let ack = acks.clone().reserve_owned().await?;
+        jobs.send(Job { row: parsed, ack }).await?;
+    }
+    drop(acks);
+    writing.await??;
+    info!("Connection from {} closed after {} rows", addr, row);
+    Ok(())
+}
+
+async fn write_acks<W: AsyncWrite + Unpin>(mut acks: Receiver<Ack>, writer: W) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    while let Some(ack) = acks.recv().await {
+        writer.write_all(ack.line().as_bytes()).await?;
+        if acks.is_empty() {
+            writer.flush().await?;
+        }
+    }
+    writer.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::Ledger;
+    use crate::stores::{MemActStore, MemTxStore};
+    use tokio::io::AsyncReadExt;
+
+    async fn start() -> (SocketAddr, SharedLedger) {
+        let ledger =
+            Ledger::new(Box::new(MemActStore::new()), Box::new(MemTxStore::new())).shared();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, ledger.clone()));
+        (addr, ledger)
+    }
+
+    async fn send(addr: SocketAddr, data: String) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(data.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn test_acknowledges_rows() {
+        let (addr, ledger) = start().await;
+        let out = send(
+            addr,
+            String::from(
+                "type,client,tx,amount\n\
+                 deposit,1,1,1.0\n\
+                 \n\
+                 withdrawal,1,2,2.0\n\
+                 deposit,1,3,1.00001\n\
+                 dispute,1,1,\n",
+            ),
+        )
+        .await;
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("1,applied,", lines[0]);
+        assert!(lines[1].starts_with("2,rejected,"));
+        assert!(lines[2].starts_with("3,rejected,") && lines[2].contains("precision"));
+        assert_eq!("4,applied,", lines[3]);
+        let ledger = ledger.lock().unwrap();
+        assert_eq!(10000, ledger.act_store().get_account(1).unwrap().held());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_connections() {
+        let (addr, ledger) = start().await;
+        let mut sending = Vec::new();
+        for client in 1..=8u16 {
+            let mut data = String::from("type,client,tx,amount\n");
+            for i in 0..1000u32 {
+                data.push_str(&format!(
+                    "deposit,{},{},0.0001\n",
+                    client,
+                    client as u32 * 10000 + i
+                ));
+            }
+            sending.push(tokio::spawn(send(addr, data)));
+        }
+        for sent in sending {
+            let out = sent.await.unwrap();
+            assert_eq!(1000, out.lines().count());
+            assert!(out.lines().all(|line| line.ends_with(",applied,")));
+        }
+        let ledger = ledger.lock().unwrap();
+        assert_eq!(8, ledger.act_store().count());
+        assert!(ledger.act_store().accounts().all(|act| act.total() == 1000));
+    }
+}
@@ -0,0 +1,196 @@
+use super::{SharedLedger, with_ledger};
+use crate::stores::{AccountFilter, AccountOrder};
+use crate::types::Account;
+use axum::extract::State;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Accounts a socket is interested in, sent by the client as
+/// `{"clients": [1, 2], "locked": true}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Subscription {
+    pub clients: BTreeSet<u16>,
+    /// Every account that gets locked
+    pub locked: bool,
+}
+
+impl Subscription {
+    pub fn matches(&self, act: &Account) -> bool {
+        self.clients.contains(&act.id()) || (self.locked && act.is_locked())
+    }
+}
+
+/// Upgrades to a WebSocket streaming account updates. Every subscription
+/// replaces the previous one and is answered with the current state of the
+/// accounts it matches, then each change to them is sent as it is applied.
+pub async fn handler(State(ledger): State<SharedLedger>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| watch(socket, ledger))
+}
+
+async fn watch(mut socket: WebSocket, ledger: SharedLedger) {
+    let mut subscription = Subscription::default();
+    let Ok(mut updates) = with_ledger(&ledger, |ledger| ledger.subscribe()).await else {
+        return;
+    };
+    loop {
+        let sent = tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(s) => {
+                        subscription = s;
+                        resubscribe(&mut socket, &ledger, &subscription, &mut updates).await
+                    }
+                    Err(e) => send(&mut socket, json!({ "error": e.to_string() })).await,
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => true,
+            },
+            update = updates.recv() => match update {
+                Ok(act) if subscription.matches(&act) => send(&mut socket, json!(act)).await,
+                Ok(_) => true,
+                // The missed updates are replaced by the current state.
+                Err(RecvError::Lagged(_)) => {
+                    resubscribe(&mut socket, &ledger, &subscription, &mut updates).await
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if !sent {
+            break;
+        }
+    }
+}
+
+/// Sends the accounts matching the subscription, updates received from now
+/// on are newer than them.
+async fn resubscribe(
+    socket: &mut WebSocket,
+    ledger: &SharedLedger,
+    subscription: &Subscription,
+    updates: &mut Receiver<Account>,
+) -> bool {
+    let subscription = subscription.clone();
+    let subscribed = with_ledger(ledger, move |ledger| {
+        let mut accounts: Vec<Account> = subscription
+            .clients
+            .iter()
+            .filter_map(|&client| ledger.act_store().get_account(client).cloned())
+            .collect();
+        if subscription.locked {
+            let locked = AccountFilter {
+                locked: Some(true),
+                ..Default::default()
+            };
+            accounts.extend(
+                ledger
+                    .act_store()
+                    .select(&locked, AccountOrder::Client)
+                    .into_iter()
+                    .filter(|act| !subscription.clients.contains(&act.id()))
+                    .cloned(),
+            );
+        }
+        (ledger.subscribe(), accounts)
+    })
+    .await;
+    let Ok((subscribed, accounts)) = subscribed else {
+        return false;
+    };
+    *updates = subscribed;
+    for act in accounts {
+        if !send(socket, json!(act)).await {
+            return false;
+        }
+    }
+    true
+}
+
+async fn send(socket: &mut WebSocket, value: serde_json::Value) -> bool {
+    socket
+        .send(Message::Text(value.to_string().into()))
+        .await
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::Ledger;
+    use crate::server::http::serve;
+    use crate::stores::{MemActStore, MemTxStore};
+    use crate::types::{Transaction, TransactionType};
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn next(socket: &mut Socket) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn tx(tx_type: TransactionType, client: u16, tx: u32, amount: u64) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() {
+        let ledger =
+            Ledger::new(Box::new(MemActStore::new()), Box::new(MemTxStore::new())).shared();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, ledger.clone()));
+        let apply = |t| {
+            ledger.lock().unwrap().apply(t).unwrap();
+        };
+        apply(tx(TransactionType::Deposit, 1, 1, 10000));
+
+        let (mut socket, _) = connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        socket
+            .send(Message::text(r#"{"clients": [1, 2]}"#))
+            .await
+            .unwrap();
+        // The current state of the known accounts comes first.
+        assert_eq!("1.0000", next(&mut socket).await["total"]);
+
+        apply(tx(TransactionType::Deposit, 3, 2, 10000));
+        apply(tx(TransactionType::Deposit, 2, 3, 20000));
+        let update = next(&mut socket).await;
+        assert_eq!(json!(2), update["client"]);
+        assert_eq!("2.0000", update["total"]);
+
+        socket
+            .send(Message::text(r#"{"clients": [1], "locked": true}"#))
+            .await
+            .unwrap();
+        assert_eq!(json!(1), next(&mut socket).await["client"]);
+        apply(tx(TransactionType::Deposit, 2, 4, 10000));
+        apply(tx(TransactionType::Dispute, 3, 2, 0));
+        apply(tx(TransactionType::Chargeback, 3, 2, 0));
+        let update = next(&mut socket).await;
+        assert_eq!(json!(3), update["client"]);
+        assert_eq!(json!(true), update["locked"]);
+
+        socket
+            .send(Message::text(r#"{"client": 1}"#))
+            .await
+            .unwrap();
+        assert!(next(&mut socket).await["error"].is_string());
+    }
+}
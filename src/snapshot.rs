@@ -0,0 +1,220 @@
+use crate::engine::Engine;
+use crate::stores::{ActStore, MemActStore, MemTxStore, TxStore};
+use crate::types::{Account, TxKind, TxRecord, TxState};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+/// State of the stores of an engine, saved as JSON to resume processing
+/// later. Amounts are integer units of 0.0001 so that restoring is exact.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub accounts: Vec<AccountState>,
+    /// Deposits, and withdrawals if they can be disputed, that can still be
+    /// referenced by disputes
+    pub transactions: Vec<DepositState>,
+    /// Input reflected in the state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mark: Option<FileMark>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AccountState {
+    pub client: u16,
+    pub total: i64,
+    pub held: u64,
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DepositState {
+    pub tx: u32,
+    pub client: u16,
+    pub amount: u64,
+    pub state: TxState,
+    #[serde(default)]
+    pub kind: TxKind,
+}
+
+/// A file and how many of its bytes have been processed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMark {
+    pub name: String,
+    pub len: u64,
+    /// FNV-1a hash of the processed bytes, absent from older snapshots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u64>,
+}
+
+impl FileMark {
+    /// Mark of the file `name` processed up to the end of `data`.
+    pub fn new(name: String, data: &[u8]) -> Self {
+        FileMark {
+            name,
+            len: data.len() as u64,
+            hash: Some(fnv1a(data)),
+        }
+    }
+
+    /// Whether `data` are the bytes the mark was made from.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.len == data.len() as u64 && self.hash.is_none_or(|hash| hash == fnv1a(data))
+    }
+}
+
+/// 64-bit FNV-1a, stable across versions unlike the std hasher.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Snapshot {
+    pub fn capture(act_store: &dyn ActStore, tx_store: &dyn TxStore) -> Result<Self> {
+        Ok(Snapshot {
+            accounts: act_store
+                .accounts()
+                .map(|act| AccountState {
+                    client: act.id(),
+                    total: act.total(),
+                    held: act.held(),
+                    locked: act.is_locked(),
+                })
+                .collect(),
+            transactions: tx_store
+                .records()?
+                .into_iter()
+                .map(|(tx, record)| DepositState {
+                    tx,
+                    client: record.client,
+                    amount: record.amount,
+                    state: record.state,
+                    kind: record.kind,
+                })
+                .collect(),
+            mark: None,
+        })
+    }
+
+    pub fn of(engine: &Engine) -> Result<Self> {
+        Snapshot::capture(engine.act_store(), engine.tx_store())
+    }
+
+    pub fn with_mark(mut self, mark: Option<FileMark>) -> Self {
+        self.mark = mark;
+        self
+    }
+
+    /// In-memory stores holding the state.
+    pub fn stores(&self) -> Result<(MemActStore, MemTxStore)> {
+        let act_store = self
+            .accounts
+            .iter()
+            .map(|act| Account::from_parts(act.client, act.total, act.held, act.locked))
+            .collect();
+        let mut tx_store = MemTxStore::new();
+        for deposit in self.transactions.iter() {
+            tx_store.insert(
+                deposit.tx,
+                TxRecord {
+                    client: deposit.client,
+                    amount: deposit.amount,
+                    state: deposit.state,
+                    kind: deposit.kind,
+                },
+            )?;
+        }
+        Ok((act_store, tx_store))
+    }
+
+    /// Snapshot saved at `path`, None if there is none yet.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid snapshot {}", path.display()))?;
+        Ok(Some(snapshot))
+    }
+
+    /// Saves the snapshot at `path`, which holds either the previous or the
+    /// new snapshot should the process stop while writing.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |w| Ok(serde_json::to_writer(w, self)?))
+    }
+}
+
+/// Replaces `path` with what `write` writes, through a temporary file
+/// renamed over it once synced.
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Transaction, TransactionType};
+
+    #[test]
+    fn test_round_trip() {
+        let mut engine = Engine::builder().build();
+        let txs = [
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Deposit, 2, 2, 25000),
+            (TransactionType::Dispute, 2, 2, 0),
+            (TransactionType::Deposit, 1, 3, 5000),
+            (TransactionType::Dispute, 1, 3, 0),
+            (TransactionType::Chargeback, 1, 3, 0),
+        ];
+        for (tx_type, client, tx, amount) in txs {
+            engine
+                .apply(Transaction {
+                    tx_type,
+                    client,
+                    tx,
+                    amount,
+                })
+                .unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        assert_eq!(None, Snapshot::read(&path).unwrap());
+        let mark = FileMark::new(String::from("a.csv"), b"type,client,tx,amount\n");
+        let snapshot = Snapshot::of(&engine).unwrap().with_mark(Some(mark));
+        snapshot.write(&path).unwrap();
+        let read = Snapshot::read(&path).unwrap().unwrap();
+        assert_eq!(snapshot, read);
+
+        let (act_store, tx_store) = read.stores().unwrap();
+        let mut restored = Engine::builder()
+            .act_store(Box::new(act_store))
+            .tx_store(Box::new(tx_store))
+            .build();
+        assert_eq!(
+            engine.accounts().collect::<Vec<_>>(),
+            restored.accounts().collect::<Vec<_>>()
+        );
+        let resolve = Transaction {
+            tx_type: TransactionType::Resolve,
+            client: 2,
+            tx: 2,
+            amount: 0,
+        };
+        assert!(restored.apply(resolve).unwrap().applied());
+        assert_eq!(25000, restored.account(2).unwrap().available().unwrap());
+    }
+}
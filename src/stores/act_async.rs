@@ -0,0 +1,138 @@
+use super::{ActStore, AsyncActStore};
+use crate::types::Account;
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Mutex};
+
+/// Exposes a synchronous [`ActStore`] as an [`AsyncActStore`].
+///
+/// Operations run on tokio's blocking thread pool so that slow stores, e.g.
+/// [`super::SqliteActStore`], do not stall the runtime. Clones share the
+/// same store.
+pub struct AsyncAdapter<S>(Arc<Mutex<S>>);
+
+impl<S: ActStore + Send + 'static> AsyncAdapter<S> {
+    pub fn new(store: S) -> Self {
+        AsyncAdapter(Arc::new(Mutex::new(store)))
+    }
+
+    /// Returns the wrapped store, fails if the adapter is still shared.
+    pub fn into_inner(self) -> Result<S> {
+        Arc::try_unwrap(self.0)
+            .map_err(|_| anyhow!("Store is still shared"))?
+            .into_inner()
+            .map_err(|_| anyhow!("Store lock poisoned"))
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut S) -> Result<T> + Send + 'static,
+    {
+        let store = self.0.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut store = store.lock().map_err(|_| anyhow!("Store lock poisoned"))?;
+            f(&mut store)
+        })
+        .await?
+    }
+}
+
+impl<S> Clone for AsyncAdapter<S> {
+    fn clone(&self) -> Self {
+        AsyncAdapter(self.0.clone())
+    }
+}
+
+impl<S: ActStore + Send + 'static> AsyncActStore for AsyncAdapter<S> {
+    async fn get_account(&self, client: u16) -> Result<Option<Account>> {
+        self.run(move |s| Ok(s.get_account(client).cloned())).await
+    }
+
+    async fn deposit(&self, client: u16, amnt: u64) -> Result<i64> {
+        self.run(move |s| s.deposit(client, amnt)).await
+    }
+
+    async fn withdraw(&self, client: u16, amnt: u64) -> Result<i64> {
+        self.run(move |s| s.withdraw(client, amnt)).await
+    }
+
+    async fn withdraw_unchecked(&self, client: u16, amnt: u64) -> Result<i64> {
+        self.run(move |s| s.withdraw_unchecked(client, amnt)).await
+    }
+
+    async fn hold(&self, client: u16, amnt: u64) -> Result<i64> {
+        self.run(move |s| s.hold(client, amnt)).await
+    }
+
+    async fn unhold(&self, client: u16, amnt: u64) -> Result<i64> {
+        self.run(move |s| s.unhold(client, amnt)).await
+    }
+
+    async fn lock_account(&self, client: u16) -> Result<bool> {
+        self.run(move |s| Ok(s.lock_account(client))).await
+    }
+
+    async fn unlock_account(&self, client: u16) -> Result<bool> {
+        self.run(move |s| Ok(s.unlock_account(client))).await
+    }
+
+    async fn accounts(&self) -> Result<Vec<Account>> {
+        self.run(|s| Ok(s.accounts().cloned().collect())).await
+    }
+
+    async fn count(&self) -> Result<usize> {
+        self.run(|s| Ok(s.count())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemActStore;
+
+    #[tokio::test]
+    async fn test_adapter_forwards_to_store() {
+        let store = AsyncAdapter::new(MemActStore::new());
+        assert_eq!(200, store.deposit(1, 200).await.unwrap());
+        assert_eq!(150, store.hold(1, 50).await.unwrap());
+        store
+            .withdraw(1, 200)
+            .await
+            .expect_err("Withdraw should fail due to held funds");
+        assert!(store.lock_account(1).await.unwrap());
+
+        let act = store.get_account(1).await.unwrap().unwrap();
+        assert_eq!(200, act.total());
+        assert_eq!(50, act.held());
+        assert!(act.is_locked());
+        assert!(store.get_account(2).await.unwrap().is_none());
+
+        store.deposit(0, 10).await.unwrap();
+        let clients: Vec<u16> = store
+            .accounts()
+            .await
+            .unwrap()
+            .iter()
+            .map(|act| act.id())
+            .collect();
+        assert_eq!(vec![0, 1], clients);
+        assert_eq!(2, store.count().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_are_owned() {
+        let store = AsyncAdapter::new(MemActStore::new());
+        store.deposit(1, 100).await.unwrap();
+        let before = store.get_account(1).await.unwrap().unwrap();
+        store.deposit(1, 100).await.unwrap();
+        assert_eq!(100, before.total());
+
+        let shared = store.clone();
+        tokio::spawn(async move { shared.withdraw(1, 50).await })
+            .await
+            .unwrap()
+            .unwrap();
+        let inner = store.into_inner().unwrap();
+        assert_eq!(150, inner.get_account(1).unwrap().total());
+    }
+}
@@ -1,10 +1,11 @@
 use super::ActStore;
 use crate::types::Account;
 use anyhow::Result;
-use std::collections::HashMap;
-use std::collections::hash_map::IntoIter;
+use std::collections::BTreeMap;
+use std::collections::btree_map::IntoIter;
+use std::ops::RangeInclusive;
 
-pub struct MemActStore(HashMap<u16, Account>);
+pub struct MemActStore(BTreeMap<u16, Account>);
 
 enum Action {
     Withdraw(u64),
@@ -17,7 +18,7 @@ impl MemActStore {
     // Creates an in-memory store for account data.
     // Useful for testing
     pub fn new() -> Self {
-        MemActStore(HashMap::new())
+        MemActStore(BTreeMap::new())
     }
 
     fn action_act(&mut self, client: u16, action: Action) -> Result<i64> {
@@ -37,6 +38,12 @@ impl Default for MemActStore {
     }
 }
 
+impl FromIterator<Account> for MemActStore {
+    fn from_iter<T: IntoIterator<Item = Account>>(iter: T) -> Self {
+        MemActStore(iter.into_iter().map(|act| (act.id(), act)).collect())
+    }
+}
+
 impl IntoIterator for MemActStore {
     type Item = (u16, Account);
 
@@ -91,11 +98,24 @@ impl ActStore for MemActStore {
     fn get_account(&self, client: u16) -> Option<&Account> {
         self.0.get(&client)
     }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.0.values())
+    }
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn range(&self, clients: RangeInclusive<u16>) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.0.range(clients).map(|(_, act)| act))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stores::AccountFilter;
 
     #[test]
     fn test_add_balance() {
@@ -188,4 +208,37 @@ mod tests {
         assert_eq!(1, act.id());
         assert!(act.is_locked());
     }
+
+    #[test]
+    fn test_enumeration() {
+        let mut store = MemActStore::new();
+        for client_id in [5, 1, 3, 2] {
+            store.deposit(client_id, 100).expect("Deposit failed");
+        }
+        store.hold(2, 50).expect("Hold failed");
+        store.lock_account(3);
+        store.withdraw_unchecked(3, 200).expect("Withdraw failed");
+
+        let ids = |acts: Box<dyn Iterator<Item = &Account> + '_>| -> Vec<u16> {
+            acts.map(|act| act.id()).collect()
+        };
+        assert_eq!(4, store.count());
+        assert_eq!(vec![1, 2, 3, 5], ids(store.accounts()));
+        assert_eq!(vec![2, 3], ids(store.range(2..=4)));
+        let locked = AccountFilter {
+            locked: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(vec![3], ids(store.query(&locked)));
+        let negative = AccountFilter {
+            negative: true,
+            ..Default::default()
+        };
+        assert_eq!(vec![3], ids(store.query(&negative)));
+        let held = AccountFilter {
+            held: true,
+            ..Default::default()
+        };
+        assert_eq!(vec![2], ids(store.query(&held)));
+    }
 }
@@ -0,0 +1,418 @@
+use super::{ActStore, SqliteTxStore};
+use crate::types::Account;
+use anyhow::{Result, anyhow};
+use log::warn;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+use std::collections::BTreeMap;
+use std::collections::btree_map::IntoIter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+pub(super) const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Schema migrations, applied in order. The index of the last applied
+/// migration + 1 is stored in SQLite's `user_version` pragma.
+/// Never edit an existing entry, append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE accounts (
+        client INTEGER PRIMARY KEY NOT NULL,
+        total INTEGER NOT NULL DEFAULT 0,
+        held INTEGER NOT NULL DEFAULT 0,
+        locked INTEGER NOT NULL DEFAULT 0
+    );",
+    "CREATE TABLE transactions (
+        tx INTEGER PRIMARY KEY NOT NULL,
+        client INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        state TEXT NOT NULL DEFAULT 'settled',
+        kind TEXT NOT NULL DEFAULT 'deposit'
+    );
+    CREATE INDEX transactions_client ON transactions (client);
+    CREATE INDEX transactions_state ON transactions (state);",
+];
+
+/// Connection shared by the account and transaction stores of a database.
+pub(super) type SharedConnection = Arc<Mutex<Connection>>;
+
+pub(super) fn lock(conn: &SharedConnection) -> Result<MutexGuard<'_, Connection>> {
+    conn.lock()
+        .map_err(|_| anyhow!("SQLite connection poisoned by a panic"))
+}
+
+/// Account store persisted in a SQLite database.
+///
+/// Every operation runs in its own SQLite transaction, unless one was opened
+/// with [`ActStore::begin`]. Reads are served from an in-memory copy of the
+/// accounts which is restored on rollback, the store therefore expects to be
+/// the only writer of its accounts.
+pub struct SqliteActStore {
+    conn: SharedConnection,
+    cache: BTreeMap<u16, Account>,
+    /// Accounts as they were before the open SQLite transaction changed them
+    rollback: Option<BTreeMap<u16, Option<Account>>>,
+}
+
+enum Action {
+    Withdraw(u64),
+    WithdrawUnchecked(u64),
+    Deposit(u64),
+    Hold(u64),
+    Unhold(u64),
+    Lock,
+    Unlock,
+}
+
+impl SqliteActStore {
+    /// Opens or creates the database at `path` and applies pending migrations.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Creates a store backed by a private in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        // Several stores may share a database, e.g. one per processing shard.
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut conn)?;
+        let cache = {
+            let mut stmt = conn.prepare("SELECT client, total, held, locked FROM accounts")?;
+            stmt.query_map([], |row| {
+                Ok(Account::from_parts(
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                ))
+            })?
+            .map(|act| act.map(|act| (act.id(), act)))
+            .collect::<rusqlite::Result<BTreeMap<_, _>>>()?
+        };
+        Ok(SqliteActStore {
+            conn: Arc::new(Mutex::new(conn)),
+            cache,
+            rollback: None,
+        })
+    }
+
+    /// Transaction store of the same database, sharing the connection so
+    /// that a transaction and its account changes are committed together.
+    pub fn tx_store(&self) -> SqliteTxStore {
+        SqliteTxStore::with_shared(self.conn.clone())
+    }
+
+    /// Schema version of the opened database.
+    pub fn schema_version(&self) -> Result<usize> {
+        Ok(lock(&self.conn)?.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    fn action_act(&mut self, client: u16, action: Action) -> Result<i64> {
+        let (act, res) = {
+            let mut conn = lock(&self.conn)?;
+            match self.rollback {
+                Some(_) => write_action(&conn, client, action)?,
+                None => {
+                    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                    let written = write_action(&tx, client, action)?;
+                    tx.commit()?;
+                    written
+                }
+            }
+        };
+        if let Some(rollback) = self.rollback.as_mut() {
+            rollback
+                .entry(client)
+                .or_insert_with(|| self.cache.get(&client).cloned());
+        }
+        self.cache.insert(client, act);
+        res
+    }
+
+    fn set_lock(&mut self, client: u16, action: Action) -> bool {
+        match self.action_act(client, action) {
+            Ok(locked) => locked != 0,
+            Err(e) => {
+                warn!("Could not update lock for client {}: {}", client, e);
+                false
+            }
+        }
+    }
+}
+
+/// Applies the action to the account row, returning the written account and
+/// the result of the operation.
+fn write_action(conn: &Connection, client: u16, action: Action) -> Result<(Account, Result<i64>)> {
+    let existing = conn
+        .query_row(
+            "SELECT total, held, locked FROM accounts WHERE client = ?1",
+            params![client],
+            |row| {
+                Ok(Account::from_parts(
+                    client,
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                ))
+            },
+        )
+        .optional()?;
+    let mut act = match (existing, &action) {
+        (Some(act), _) => act,
+        (None, Action::Withdraw(_) | Action::Deposit(_) | Action::Hold(_) | Action::Unhold(_)) => {
+            Account::new(client)
+        }
+        (None, _) => return Err(anyhow!("Account not found for client {}", client)),
+    };
+    // Account operations leave the account untouched when they fail, the
+    // row is written regardless so that new accounts are created like
+    // they are in the in-memory store.
+    let res = match action {
+        Action::Withdraw(amnt) => act.withdraw(amnt),
+        Action::WithdrawUnchecked(amnt) => act.withdraw_allow_negative(amnt),
+        Action::Deposit(amnt) => act.deposit(amnt),
+        Action::Hold(amnt) => act.hold(amnt),
+        Action::Unhold(amnt) => act.unhold(amnt),
+        Action::Lock => Ok(i64::from(act.lock())),
+        Action::Unlock => Ok(i64::from(act.unlock())),
+    };
+    conn.execute(
+        "INSERT INTO accounts (client, total, held, locked) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(client) DO UPDATE SET
+                total = excluded.total, held = excluded.held, locked = excluded.locked",
+        params![client, act.total(), act.held(), act.is_locked()],
+    )?;
+    Ok((act, res))
+}
+
+pub(super) fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than supported version {}",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+impl IntoIterator for SqliteActStore {
+    type Item = (u16, Account);
+
+    type IntoIter = IntoIter<u16, Account>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cache.into_iter()
+    }
+}
+
+impl ActStore for SqliteActStore {
+    fn deposit(&mut self, client: u16, amnt: u64) -> Result<i64> {
+        self.action_act(client, Action::Deposit(amnt))
+    }
+
+    fn withdraw(&mut self, client: u16, amnt: u64) -> Result<i64> {
+        self.action_act(client, Action::Withdraw(amnt))
+    }
+
+    fn withdraw_unchecked(&mut self, client: u16, amnt: u64) -> Result<i64> {
+        self.action_act(client, Action::WithdrawUnchecked(amnt))
+    }
+
+    fn hold(&mut self, client: u16, amnt: u64) -> Result<i64> {
+        self.action_act(client, Action::Hold(amnt))
+    }
+
+    fn unhold(&mut self, client: u16, amnt: u64) -> Result<i64> {
+        self.action_act(client, Action::Unhold(amnt))
+    }
+
+    fn lock_account(&mut self, client: u16) -> bool {
+        self.set_lock(client, Action::Lock)
+    }
+
+    fn unlock_account(&mut self, client: u16) -> bool {
+        self.set_lock(client, Action::Unlock)
+    }
+
+    fn get_account(&self, client: u16) -> Option<&Account> {
+        self.cache.get(&client)
+    }
+
+    fn begin(&mut self) -> Result<()> {
+        lock(&self.conn)?.execute_batch("BEGIN IMMEDIATE")?;
+        self.rollback = Some(BTreeMap::new());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        lock(&self.conn)?.execute_batch("COMMIT")?;
+        self.rollback = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        if let Err(e) = lock(&self.conn).and_then(|conn| Ok(conn.execute_batch("ROLLBACK")?)) {
+            warn!("Could not roll back: {}", e);
+        }
+        for (client, act) in self.rollback.take().into_iter().flatten() {
+            match act {
+                Some(act) => self.cache.insert(client, act),
+                None => self.cache.remove(&client),
+            };
+        }
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.cache.values())
+    }
+
+    fn count(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn range(&self, clients: RangeInclusive<u16>) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.cache.range(clients).map(|(_, act)| act))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_balance() {
+        let mut store = SqliteActStore::open_in_memory().unwrap();
+        let client_id = 1;
+        store.deposit(client_id, 200).expect("Deposit failed");
+        let balance = store.deposit(client_id, 50);
+        assert!(matches!(balance, Ok(250)));
+
+        let act = store
+            .get_account(client_id)
+            .expect("Could not get account from store");
+        assert_eq!(client_id, act.id());
+        assert!(matches!(act.available(), Ok(250)));
+    }
+
+    #[test]
+    fn test_negative_balance() {
+        let mut store = SqliteActStore::open_in_memory().unwrap();
+        let client_id = 1;
+        store
+            .withdraw(client_id, 1)
+            .expect_err("Withdraw should fail");
+
+        let act = store
+            .get_account(client_id)
+            .expect("Could not get account from store");
+        assert!(matches!(act.available(), Ok(0)));
+    }
+
+    #[test]
+    fn test_hold_and_lock() {
+        let mut store = SqliteActStore::open_in_memory().unwrap();
+        let client_id = 1;
+        store.deposit(client_id, 100).expect("Deposit failed");
+        assert!(matches!(store.hold(client_id, 100), Ok(0)));
+        store
+            .unhold(client_id, 200)
+            .expect_err("Unhold should fail");
+        assert!(store.lock_account(client_id));
+        assert!(!store.lock_account(2));
+        store
+            .withdraw_unchecked(2, 1)
+            .expect_err("Unchecked withdraw needs an existing account");
+
+        let act = store.get_account(client_id).unwrap();
+        assert_eq!(100, act.held());
+        assert!(act.is_locked());
+        assert!(store.get_account(2).is_none());
+    }
+
+    #[test]
+    fn test_state_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("act.db");
+        {
+            let mut store = SqliteActStore::open(&path).unwrap();
+            store.deposit(1, 300).unwrap();
+            store.hold(1, 100).unwrap();
+            store.deposit(2, 10).unwrap();
+            store.lock_account(2);
+        }
+        let store = SqliteActStore::open(&path).unwrap();
+        assert_eq!(MIGRATIONS.len(), store.schema_version().unwrap());
+        assert_eq!(
+            Some(&Account::from_parts(1, 300, 100, false)),
+            store.get_account(1)
+        );
+        assert_eq!(
+            Some(&Account::from_parts(2, 10, 0, true)),
+            store.get_account(2)
+        );
+        assert_eq!(2, store.count());
+        let clients: Vec<u16> = store.range(2..=9).map(|act| act.id()).collect();
+        assert_eq!(vec![2], clients);
+        let clients: Vec<u16> = store.into_iter().map(|(c, _)| c).collect();
+        assert_eq!(vec![1, 2], clients);
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let mut store = SqliteActStore::open_in_memory().unwrap();
+        store.deposit(1, 100).unwrap();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject BEFORE UPDATE ON accounts
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+            )
+            .unwrap();
+        store
+            .deposit(1, 100)
+            .expect_err("Update should be rejected");
+        assert_eq!(100, store.get_account(1).unwrap().total());
+        let total: i64 = store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT total FROM accounts WHERE client = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(100, total);
+    }
+
+    #[test]
+    fn test_rollback_restores_accounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("act.db");
+        let mut store = SqliteActStore::open(&path).unwrap();
+        store.deposit(1, 100).unwrap();
+        store.begin().unwrap();
+        store.deposit(1, 50).unwrap();
+        store.deposit(2, 50).unwrap();
+        store.rollback();
+        assert_eq!(100, store.get_account(1).unwrap().total());
+        assert!(store.get_account(2).is_none());
+        store.begin().unwrap();
+        store.withdraw(1, 30).unwrap();
+        store.commit().unwrap();
+        let store = SqliteActStore::open(&path).unwrap();
+        assert_eq!(70, store.get_account(1).unwrap().total());
+        assert_eq!(1, store.count());
+    }
+}
@@ -1,8 +1,23 @@
 pub mod act_mem;
 pub use act_mem::MemActStore;
+pub mod act_async;
+pub use act_async::AsyncAdapter;
+pub mod act_sqlite;
+pub use act_sqlite::SqliteActStore;
+pub mod query;
+pub use query::{AccountFilter, AccountOrder};
+pub mod tx_mem;
+pub use tx_mem::MemTxStore;
+pub mod tx_spill;
+pub use tx_spill::SpillTxStore;
+pub mod tx_sqlite;
+pub use tx_sqlite::SqliteTxStore;
 
-use crate::types::Account;
+use crate::types::{Account, TxRecord, TxState};
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::ops::RangeInclusive;
 
 pub trait ActStore {
     /// Trait to be implemented by account stores
@@ -14,4 +29,102 @@ pub trait ActStore {
     fn unhold(&mut self, client: u16, amnt: u64) -> Result<i64>;
     fn lock_account(&mut self, client: u16) -> bool;
     fn unlock_account(&mut self, client: u16) -> bool;
+    /// Accounts ordered by client id.
+    fn accounts(&self) -> Box<dyn Iterator<Item = &Account> + '_>;
+
+    /// Starts grouping the following operations, together with the writes
+    /// of a transaction store sharing the storage, until [`ActStore::commit`]
+    /// or [`ActStore::rollback`]. Stores without such transactions apply
+    /// every operation at once.
+    fn begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Undoes the operations since [`ActStore::begin`].
+    fn rollback(&mut self) {}
+
+    fn count(&self) -> usize {
+        self.accounts().count()
+    }
+
+    /// Accounts with a client id within `clients`, ordered by client id.
+    fn range(&self, clients: RangeInclusive<u16>) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(
+            self.accounts()
+                .filter(move |act| clients.contains(&act.id())),
+        )
+    }
+
+    /// Accounts matching `filter`, ordered by client id.
+    fn query<'a>(
+        &'a self,
+        filter: &'a AccountFilter,
+    ) -> Box<dyn Iterator<Item = &'a Account> + 'a> {
+        match &filter.clients {
+            Some(clients) => Box::new(
+                clients
+                    .iter()
+                    .filter_map(|client| self.get_account(*client))
+                    .filter(move |act| filter.matches(act)),
+            ),
+            None => Box::new(self.accounts().filter(move |act| filter.matches(act))),
+        }
+    }
+
+    /// Accounts matching `filter` in the given order.
+    fn select<'a>(&'a self, filter: &'a AccountFilter, order: AccountOrder) -> Vec<&'a Account> {
+        let mut accounts: Vec<&Account> = self.query(filter).collect();
+        order.sort(&mut accounts);
+        accounts
+    }
+}
+
+/// Companion of [`ActStore`] for backends doing I/O, returns owned snapshots
+/// of accounts so that no borrow of the store is held across awaits.
+pub trait AsyncActStore {
+    fn get_account(&self, client: u16) -> impl Future<Output = Result<Option<Account>>> + Send;
+    fn deposit(&self, client: u16, amnt: u64) -> impl Future<Output = Result<i64>> + Send;
+    fn withdraw(&self, client: u16, amnt: u64) -> impl Future<Output = Result<i64>> + Send;
+    fn withdraw_unchecked(
+        &self,
+        client: u16,
+        amnt: u64,
+    ) -> impl Future<Output = Result<i64>> + Send;
+    fn hold(&self, client: u16, amnt: u64) -> impl Future<Output = Result<i64>> + Send;
+    fn unhold(&self, client: u16, amnt: u64) -> impl Future<Output = Result<i64>> + Send;
+    fn lock_account(&self, client: u16) -> impl Future<Output = Result<bool>> + Send;
+    fn unlock_account(&self, client: u16) -> impl Future<Output = Result<bool>> + Send;
+    /// Snapshot of every account, ordered by client id.
+    fn accounts(&self) -> impl Future<Output = Result<Vec<Account>>> + Send;
+    fn count(&self) -> impl Future<Output = Result<usize>> + Send;
+}
+
+pub trait TxStore {
+    /// Trait to be implemented by transaction stores.
+    /// Inserting an existing id replaces the stored record. Processing never
+    /// does: a deposit or withdrawal reusing a stored id is rejected before
+    /// it reaches the store, whichever store is used.
+    fn insert(&mut self, tx: u32, record: TxRecord) -> Result<()>;
+    fn get(&self, tx: u32) -> Result<Option<TxRecord>>;
+    /// Fails if the transaction is not in the store.
+    fn set_state(&mut self, tx: u32, state: TxState) -> Result<()>;
+    /// Transactions of a client, ordered by transaction id.
+    fn client_txs(&self, client: u16) -> Result<Vec<(u32, TxRecord)>>;
+    /// Transactions currently disputed, ordered by transaction id.
+    fn disputed(&self) -> Result<Vec<(u32, TxRecord)>>;
+    /// Every transaction, ordered by transaction id.
+    fn records(&self) -> Result<Vec<(u32, TxRecord)>>;
+
+    /// Number of open disputes per client.
+    fn dispute_counts(&self) -> Result<BTreeMap<u16, usize>> {
+        let mut counts = BTreeMap::new();
+        for (_, record) in self.disputed()? {
+            *counts.entry(record.client).or_default() += 1;
+        }
+        Ok(counts)
+    }
 }
@@ -0,0 +1,112 @@
+use crate::types::Account;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+/// Criteria for [`super::ActStore::query`], every criterion set must match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountFilter {
+    pub locked: Option<bool>,
+    /// Only accounts with a negative total
+    pub negative: bool,
+    /// Only accounts with held funds
+    pub held: bool,
+    pub min_total: Option<i64>,
+    /// Only these clients
+    pub clients: Option<BTreeSet<u16>>,
+}
+
+impl AccountFilter {
+    pub fn matches(&self, act: &Account) -> bool {
+        self.locked.is_none_or(|locked| act.is_locked() == locked)
+            && (!self.negative || act.total() < 0)
+            && (!self.held || act.held() > 0)
+            && self.min_total.is_none_or(|min| act.total() >= min)
+            && self
+                .clients
+                .as_ref()
+                .is_none_or(|clients| clients.contains(&act.id()))
+    }
+}
+
+/// Order of [`super::ActStore::select`], ties are ordered by client id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountOrder {
+    #[default]
+    Client,
+    /// Largest total first
+    Balance,
+    /// Locked accounts first
+    Locked,
+}
+
+impl AccountOrder {
+    /// Sorts accounts given in client id order.
+    pub fn sort(self, accounts: &mut [&Account]) {
+        match self {
+            AccountOrder::Client => {}
+            AccountOrder::Balance => accounts.sort_by_key(|act| Reverse(act.total())),
+            AccountOrder::Locked => accounts.sort_by_key(|act| !act.is_locked()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_all() {
+        let filter = AccountFilter::default();
+        assert!(filter.matches(&Account::new(1)));
+        assert!(filter.matches(&Account::from_parts(1, -5, 10, true)));
+    }
+
+    #[test]
+    fn test_criteria_are_combined() {
+        let filter = AccountFilter {
+            locked: Some(true),
+            negative: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&Account::from_parts(1, -5, 0, true)));
+        assert!(!filter.matches(&Account::from_parts(1, -5, 0, false)));
+        assert!(!filter.matches(&Account::from_parts(1, 5, 0, true)));
+
+        let filter = AccountFilter {
+            locked: Some(false),
+            held: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&Account::from_parts(1, 5, 5, false)));
+        assert!(!filter.matches(&Account::from_parts(1, 5, 0, false)));
+
+        let filter = AccountFilter {
+            min_total: Some(-5),
+            clients: Some(BTreeSet::from([1, 2])),
+            ..Default::default()
+        };
+        assert!(filter.matches(&Account::from_parts(1, -5, 0, false)));
+        assert!(!filter.matches(&Account::from_parts(1, -6, 0, false)));
+        assert!(!filter.matches(&Account::from_parts(3, 5, 0, false)));
+    }
+
+    #[test]
+    fn test_order_is_stable() {
+        let accounts = [
+            Account::from_parts(1, 5, 0, false),
+            Account::from_parts(2, 10, 0, true),
+            Account::from_parts(3, 5, 0, true),
+            Account::from_parts(4, -1, 0, false),
+        ];
+        let sorted = |order: AccountOrder| -> Vec<u16> {
+            let mut acts: Vec<&Account> = accounts.iter().collect();
+            order.sort(&mut acts);
+            acts.iter().map(|act| act.id()).collect()
+        };
+        assert_eq!(vec![1, 2, 3, 4], sorted(AccountOrder::Client));
+        assert_eq!(vec![2, 1, 3, 4], sorted(AccountOrder::Balance));
+        assert_eq!(vec![2, 3, 1, 4], sorted(AccountOrder::Locked));
+    }
+}
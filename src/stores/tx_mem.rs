@@ -0,0 +1,135 @@
+use super::TxStore;
+use crate::types::{TxRecord, TxState};
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+/// Keeps every transaction in memory.
+pub struct MemTxStore(HashMap<u32, TxRecord>);
+
+impl MemTxStore {
+    pub fn new() -> Self {
+        MemTxStore(HashMap::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Default for MemTxStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxStore for MemTxStore {
+    fn insert(&mut self, tx: u32, record: TxRecord) -> Result<()> {
+        self.0.insert(tx, record);
+        Ok(())
+    }
+
+    fn get(&self, tx: u32) -> Result<Option<TxRecord>> {
+        Ok(self.0.get(&tx).copied())
+    }
+
+    fn set_state(&mut self, tx: u32, state: TxState) -> Result<()> {
+        self.0
+            .get_mut(&tx)
+            .map(|record| record.state = state)
+            .ok_or(anyhow!("Transaction {} not found", tx))
+    }
+
+    fn client_txs(&self, client: u16) -> Result<Vec<(u32, TxRecord)>> {
+        let mut txs: Vec<(u32, TxRecord)> = self
+            .0
+            .iter()
+            .filter(|(_, record)| record.client == client)
+            .map(|(tx, record)| (*tx, *record))
+            .collect();
+        txs.sort_unstable_by_key(|(tx, _)| *tx);
+        Ok(txs)
+    }
+
+    fn disputed(&self) -> Result<Vec<(u32, TxRecord)>> {
+        let mut txs: Vec<(u32, TxRecord)> = self
+            .0
+            .iter()
+            .filter(|(_, record)| record.state == TxState::Disputed)
+            .map(|(tx, record)| (*tx, *record))
+            .collect();
+        txs.sort_unstable_by_key(|(tx, _)| *tx);
+        Ok(txs)
+    }
+
+    fn records(&self) -> Result<Vec<(u32, TxRecord)>> {
+        let mut txs: Vec<(u32, TxRecord)> =
+            self.0.iter().map(|(tx, record)| (*tx, *record)).collect();
+        txs.sort_unstable_by_key(|(tx, _)| *tx);
+        Ok(txs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TxKind;
+
+    fn record(client: u16, amount: u64) -> TxRecord {
+        TxRecord {
+            client,
+            amount,
+            state: TxState::Settled,
+            kind: TxKind::Deposit,
+        }
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut store = MemTxStore::new();
+        store.insert(1, record(1, 100)).unwrap();
+        assert_eq!(Some(record(1, 100)), store.get(1).unwrap());
+        assert_eq!(None, store.get(2).unwrap());
+        store.insert(1, record(2, 50)).unwrap();
+        assert_eq!(Some(record(2, 50)), store.get(1).unwrap());
+        assert_eq!(1, store.len());
+    }
+
+    #[test]
+    fn test_set_state() {
+        let mut store = MemTxStore::new();
+        store.insert(1, record(1, 100)).unwrap();
+        store.insert(2, record(1, 100)).unwrap();
+        store.set_state(1, TxState::Disputed).unwrap();
+        store.set_state(2, TxState::ChargedBack).unwrap();
+        assert_eq!(TxState::Disputed, store.get(1).unwrap().unwrap().state);
+        assert_eq!(
+            vec![1],
+            store
+                .disputed()
+                .unwrap()
+                .iter()
+                .map(|(tx, _)| *tx)
+                .collect::<Vec<_>>()
+        );
+        store
+            .set_state(3, TxState::Disputed)
+            .expect_err("Unknown transactions cannot be disputed");
+    }
+
+    #[test]
+    fn test_client_txs() {
+        let mut store = MemTxStore::new();
+        store.insert(3, record(1, 30)).unwrap();
+        store.insert(2, record(2, 20)).unwrap();
+        store.insert(1, record(1, 10)).unwrap();
+        assert_eq!(
+            vec![(1, record(1, 10)), (3, record(1, 30))],
+            store.client_txs(1).unwrap()
+        );
+        assert!(store.client_txs(3).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+use super::TxStore;
+use crate::types::{TxKind, TxRecord, TxState};
+use anyhow::{Result, anyhow};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// On-disk record: tx (u32), client (u16), amount (u64), state (u8), kind (u8),
+/// little endian.
+const RECORD_SIZE: usize = 16;
+const STATE_OFFSET: u64 = 14;
+/// One in-memory key is kept for every `FENCE` records of a run so that a
+/// lookup costs a single read of at most `FENCE * RECORD_SIZE` bytes.
+const FENCE: usize = 512;
+/// Memory of one hash map bucket: the entry and its control byte.
+const BUCKET_SIZE: usize = size_of::<(u32, TxRecord)>() + 1;
+/// The newest run is merged into the one before it unless that one holds
+/// more than this many times its records. Run sizes then grow geometrically
+/// from newest to oldest, there are a logarithmic number of them and each
+/// record is rewritten a logarithmic number of times.
+const TIER_RATIO: usize = 2;
+
+/// Transaction store keeping recent transactions in memory and spilling
+/// older ones to sorted run files once the memory budget is reached.
+///
+/// Lookups check the in-memory set first, then runs from newest to oldest,
+/// so a re-inserted id shadows older entries just like with [`super::MemTxStore`].
+/// Run files are scratch data written to a subdirectory of its own, so that
+/// stores of several processes can share a spill directory. They are deleted
+/// when the store is dropped, the spill directory itself only if it is the
+/// temporary one of [`SpillTxStore::temporary`].
+pub struct SpillTxStore {
+    hot: HashMap<u32, TxRecord>,
+    /// Oldest first
+    runs: Vec<Run>,
+    /// Subdirectory of this store in the spill directory
+    dir: PathBuf,
+    /// Spill directory to remove once no store uses it
+    temporary: Option<PathBuf>,
+    max_hot: usize,
+    next_run: usize,
+}
+
+struct Run {
+    path: PathBuf,
+    file: RefCell<File>,
+    len: usize,
+    fences: Vec<u32>,
+}
+
+impl SpillTxStore {
+    /// Creates a store spilling to `dir`, keeping at most about
+    /// `mem_budget` bytes of transactions in memory.
+    pub fn new<P: AsRef<Path>>(dir: P, mem_budget: usize) -> Result<Self> {
+        let parent = dir.as_ref();
+        fs::create_dir_all(parent)?;
+        Ok(SpillTxStore {
+            hot: HashMap::new(),
+            runs: Vec::new(),
+            dir: create_store_dir(parent)?,
+            temporary: None,
+            max_hot: max_hot(mem_budget),
+            next_run: 0,
+        })
+    }
+
+    /// Same as [`SpillTxStore::new`], spilling to a directory of the process
+    /// in the system temporary directory.
+    pub fn temporary(mem_budget: usize) -> Result<Self> {
+        let parent = env::temp_dir().join(format!("act-spill-{}", std::process::id()));
+        let mut store = Self::new(&parent, mem_budget)?;
+        store.temporary = Some(parent);
+        Ok(store)
+    }
+
+    /// Number of transactions currently held in memory.
+    pub fn hot_len(&self) -> usize {
+        self.hot.len()
+    }
+
+    /// Number of run files on disk.
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let mut entries: Vec<(u32, TxRecord)> = self.hot.drain().collect();
+        entries.sort_unstable_by_key(|(tx, _)| *tx);
+        let run = self.write_run(entries.into_iter().map(Ok))?;
+        self.runs.push(run);
+        while let [.., older, newer] = self.runs.as_slice()
+            && older.len <= newer.len * TIER_RATIO
+        {
+            self.merge_newest()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the two newest runs into one, dropping shadowed entries.
+    fn merge_newest(&mut self) -> Result<()> {
+        let merged = self.runs.split_off(self.runs.len() - 2);
+        let readers = merged
+            .iter()
+            .map(|run| run.reader())
+            .collect::<Result<Vec<_>>>()?;
+        let run = self.write_run(Merge::new(readers)?)?;
+        for old in merged {
+            fs::remove_file(&old.path)?;
+        }
+        self.runs.push(run);
+        Ok(())
+    }
+
+    fn write_run<I>(&mut self, entries: I) -> Result<Run>
+    where
+        I: Iterator<Item = Result<(u32, TxRecord)>>,
+    {
+        let path = self.dir.join(format!("run-{:06}.bin", self.next_run));
+        self.next_run += 1;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut writer = BufWriter::new(&file);
+        let mut len = 0;
+        let mut fences = Vec::new();
+        for entry in entries {
+            let (tx, record) = entry?;
+            if len % FENCE == 0 {
+                fences.push(tx);
+            }
+            writer.write_all(&record.to_bytes(tx))?;
+            len += 1;
+        }
+        writer.flush()?;
+        drop(writer);
+        Ok(Run {
+            path,
+            file: RefCell::new(file),
+            len,
+            fences,
+        })
+    }
+
+    /// Reads every run and the in-memory set, returning the current entries
+    /// accepted by `keep` ordered by tx id.
+    fn scan<F: Fn(&TxRecord) -> bool>(&self, keep: F) -> Result<Vec<(u32, TxRecord)>> {
+        let readers = self
+            .runs
+            .iter()
+            .map(|run| run.reader())
+            .collect::<Result<Vec<_>>>()?;
+        let mut txs = Vec::new();
+        for entry in Merge::new(readers)? {
+            let (tx, record) = entry?;
+            if keep(&record) && !self.hot.contains_key(&tx) {
+                txs.push((tx, record));
+            }
+        }
+        txs.extend(
+            self.hot
+                .iter()
+                .filter(|(_, record)| keep(record))
+                .map(|(tx, record)| (*tx, *record)),
+        );
+        txs.sort_unstable_by_key(|(tx, _)| *tx);
+        Ok(txs)
+    }
+
+    /// Finds the newest run containing `tx` and the record's index in it.
+    fn find(&self, tx: u32) -> Result<Option<(&Run, usize, TxRecord)>> {
+        for run in self.runs.iter().rev() {
+            if let Some((idx, record)) = run.find(tx)? {
+                return Ok(Some((run, idx, record)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Creates a directory in `parent` that no other store uses, named after
+/// the process and a counter of the stores it created.
+fn create_store_dir(parent: &Path) -> Result<PathBuf> {
+    static STORES: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let dir = parent.join(format!(
+            "{}-{}",
+            std::process::id(),
+            STORES.fetch_add(1, Ordering::Relaxed)
+        ));
+        // A directory left by a process that had the same id is skipped.
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Most entries of a hash map whose table fits in `mem_budget` bytes. Tables
+/// have a power of two number of buckets and keep 1/8 of them empty.
+fn max_hot(mem_budget: usize) -> usize {
+    let buckets = (mem_budget / BUCKET_SIZE)
+        .checked_ilog2()
+        .map_or(0, |exp| 1 << exp);
+    (buckets / 8 * 7).max(1)
+}
+
+impl Run {
+    fn find(&self, tx: u32) -> Result<Option<(usize, TxRecord)>> {
+        let block = match self.fences.partition_point(|&k| k <= tx) {
+            0 => return Ok(None),
+            b => b - 1,
+        };
+        let start = block * FENCE;
+        let count = FENCE.min(self.len - start);
+        let mut buf = vec![0u8; count * RECORD_SIZE];
+        {
+            let mut file = self.file.borrow_mut();
+            file.seek(SeekFrom::Start((start * RECORD_SIZE) as u64))?;
+            file.read_exact(&mut buf)?;
+        }
+        let records: Vec<&[u8]> = buf.chunks_exact(RECORD_SIZE).collect();
+        Ok(records
+            .binary_search_by_key(&tx, |r| decode(r).0)
+            .ok()
+            .map(|i| (start + i, decode(records[i]).1)))
+    }
+
+    fn set_state(&self, idx: usize, state: TxState) -> Result<()> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start((idx * RECORD_SIZE) as u64 + STATE_OFFSET))?;
+        file.write_all(&[encode_state(state)])?;
+        Ok(())
+    }
+
+    fn reader(&self) -> Result<RunReader> {
+        Ok(RunReader {
+            reader: BufReader::new(File::open(&self.path)?),
+            remaining: self.len,
+        })
+    }
+}
+
+struct RunReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl Iterator for RunReader {
+    type Item = Result<(u32, TxRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut buf = [0u8; RECORD_SIZE];
+        Some(
+            self.reader
+                .read_exact(&mut buf)
+                .map(|_| decode(&buf))
+                .map_err(Into::into),
+        )
+    }
+}
+
+/// Merges sorted runs, yielding each id once with the entry of the newest run.
+struct Merge {
+    readers: Vec<RunReader>,
+    heads: Vec<Option<TxRecord>>,
+    /// Ordered by tx id, then newest run first.
+    heap: BinaryHeap<Reverse<(u32, Reverse<usize>)>>,
+    last: Option<u32>,
+}
+
+impl Merge {
+    fn new(readers: Vec<RunReader>) -> Result<Self> {
+        let mut merge = Merge {
+            heads: vec![None; readers.len()],
+            readers,
+            heap: BinaryHeap::new(),
+            last: None,
+        };
+        for i in 0..merge.readers.len() {
+            merge.advance(i)?;
+        }
+        Ok(merge)
+    }
+
+    fn advance(&mut self, i: usize) -> Result<()> {
+        if let Some((tx, record)) = self.readers[i].next().transpose()? {
+            self.heads[i] = Some(record);
+            self.heap.push(Reverse((tx, Reverse(i))));
+        }
+        Ok(())
+    }
+
+    fn next_entry(&mut self) -> Result<Option<(u32, TxRecord)>> {
+        while let Some(Reverse((tx, Reverse(i)))) = self.heap.pop() {
+            let record = self.heads[i].take().expect("heap entries have a head");
+            self.advance(i)?;
+            if self.last != Some(tx) {
+                self.last = Some(tx);
+                return Ok(Some((tx, record)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<(u32, TxRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+impl TxRecord {
+    fn to_bytes(self, tx: u32) -> [u8; RECORD_SIZE] {
+        let mut buf = [0u8; RECORD_SIZE];
+        buf[0..4].copy_from_slice(&tx.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.client.to_le_bytes());
+        buf[6..14].copy_from_slice(&self.amount.to_le_bytes());
+        buf[14] = encode_state(self.state);
+        buf[15] = match self.kind {
+            TxKind::Deposit => 0,
+            TxKind::Withdrawal => 1,
+        };
+        buf
+    }
+}
+
+fn decode(buf: &[u8]) -> (u32, TxRecord) {
+    let tx = u32::from_le_bytes(buf[0..4].try_into().expect("4 bytes"));
+    let record = TxRecord {
+        client: u16::from_le_bytes(buf[4..6].try_into().expect("2 bytes")),
+        amount: u64::from_le_bytes(buf[6..14].try_into().expect("8 bytes")),
+        state: decode_state(buf[14]),
+        kind: match buf[15] {
+            1 => TxKind::Withdrawal,
+            _ => TxKind::Deposit,
+        },
+    };
+    (tx, record)
+}
+
+fn encode_state(state: TxState) -> u8 {
+    match state {
+        TxState::Settled => 0,
+        TxState::Disputed => 1,
+        TxState::ChargedBack => 2,
+    }
+}
+
+fn decode_state(byte: u8) -> TxState {
+    match byte {
+        1 => TxState::Disputed,
+        2 => TxState::ChargedBack,
+        _ => TxState::Settled,
+    }
+}
+
+impl Drop for SpillTxStore {
+    fn drop(&mut self) {
+        for run in self.runs.drain(..) {
+            let _ = fs::remove_file(&run.path);
+        }
+        let _ = fs::remove_dir(&self.dir);
+        if let Some(parent) = &self.temporary {
+            // Only succeeds if no other store uses the spill directory.
+            let _ = fs::remove_dir(parent);
+        }
+    }
+}
+
+impl TxStore for SpillTxStore {
+    fn insert(&mut self, tx: u32, record: TxRecord) -> Result<()> {
+        self.hot.insert(tx, record);
+        // Spilling before the set outgrows `max_hot` keeps the table from
+        // growing past the budget.
+        if self.hot.len() >= self.max_hot {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, tx: u32) -> Result<Option<TxRecord>> {
+        if let Some(record) = self.hot.get(&tx) {
+            return Ok(Some(*record));
+        }
+        Ok(self.find(tx)?.map(|(_, _, record)| record))
+    }
+
+    fn set_state(&mut self, tx: u32, state: TxState) -> Result<()> {
+        if let Some(record) = self.hot.get_mut(&tx) {
+            record.state = state;
+            return Ok(());
+        }
+        match self.find(tx)? {
+            Some((run, idx, _)) => run.set_state(idx, state),
+            None => Err(anyhow!("Transaction {} not found", tx)),
+        }
+    }
+
+    fn client_txs(&self, client: u16) -> Result<Vec<(u32, TxRecord)>> {
+        self.scan(|record| record.client == client)
+    }
+
+    fn disputed(&self) -> Result<Vec<(u32, TxRecord)>> {
+        self.scan(|record| record.state == TxState::Disputed)
+    }
+
+    fn records(&self) -> Result<Vec<(u32, TxRecord)>> {
+        self.scan(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store spilling once it holds `max_hot` transactions.
+    fn store(dir: &Path, max_hot: usize) -> SpillTxStore {
+        let mut store = SpillTxStore::new(dir, 0).unwrap();
+        store.max_hot = max_hot;
+        store
+    }
+
+    fn record(client: u16, amount: u64) -> TxRecord {
+        TxRecord {
+            client,
+            amount,
+            state: TxState::Settled,
+            kind: TxKind::Deposit,
+        }
+    }
+
+    #[test]
+    fn test_spills_over_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 10);
+        for tx in 0..25 {
+            store.insert(tx, record(1, tx as u64)).unwrap();
+        }
+        // Two spills of 10, merged as they are the same size.
+        assert_eq!(1, store.run_count());
+        assert!(store.hot_len() <= 10);
+        for tx in 0..25 {
+            assert_eq!(Some(record(1, tx as u64)), store.get(tx).unwrap());
+        }
+        assert_eq!(None, store.get(25).unwrap());
+    }
+
+    #[test]
+    fn test_hot_set_fits_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let budget = 1024 * 1024;
+        let mut store = SpillTxStore::new(dir.path(), budget).unwrap();
+        let mut largest = 0;
+        for tx in 0..100_000 {
+            store.insert(tx, record(1, 1)).unwrap();
+            largest = largest.max(store.hot.capacity());
+        }
+        assert!(store.run_count() > 0);
+        assert!(largest / 7 * 8 * BUCKET_SIZE <= budget);
+        assert!(largest * 2 * BUCKET_SIZE > budget);
+    }
+
+    #[test]
+    fn test_dispute_spilled_tx() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 1);
+        store.insert(7, record(1, 100)).unwrap();
+        store.insert(8, record(1, 200)).unwrap();
+        assert!(store.run_count() > 0);
+        store.set_state(7, TxState::Disputed).unwrap();
+        assert_eq!(TxState::Disputed, store.get(7).unwrap().unwrap().state);
+        store.set_state(7, TxState::ChargedBack).unwrap();
+        assert_eq!(TxState::ChargedBack, store.get(7).unwrap().unwrap().state);
+        store
+            .set_state(9, TxState::Disputed)
+            .expect_err("Unknown transactions cannot be disputed");
+    }
+
+    #[test]
+    fn test_newer_entries_shadow_spilled_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 2);
+        for tx in 0..3 {
+            store.insert(tx, record(1, 1)).unwrap();
+        }
+        store.insert(1, record(2, 2)).unwrap();
+        assert_eq!(Some(record(2, 2)), store.get(1).unwrap());
+        store.set_state(1, TxState::Disputed).unwrap();
+        assert_eq!(TxState::Disputed, store.get(1).unwrap().unwrap().state);
+        let client_1: Vec<u32> = store
+            .client_txs(1)
+            .unwrap()
+            .into_iter()
+            .map(|(tx, _)| tx)
+            .collect();
+        assert_eq!(vec![0, 2], client_1);
+        assert_eq!(
+            vec![(1, store.get(1).unwrap().unwrap())],
+            store.disputed().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compaction_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 1);
+        for round in 0..16 {
+            store.insert(1, record(1, round)).unwrap();
+            store.insert(2000 + round as u32, record(2, round)).unwrap();
+        }
+        store.set_state(1, TxState::Disputed).unwrap();
+        let latest = store.get(1).unwrap().unwrap();
+        assert_eq!(TxState::Disputed, latest.state);
+        assert_eq!(15, latest.amount);
+        assert_eq!(16, store.client_txs(2).unwrap().len());
+    }
+
+    #[test]
+    fn test_runs_are_tiered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 100);
+        for tx in 0..100_000 {
+            store.insert(tx, record(1, 1)).unwrap();
+        }
+        // 1000 spills of 100 records.
+        let lens: Vec<usize> = store.runs.iter().map(|run| run.len).collect();
+        assert!(lens.len() <= 10, "{:?}", lens);
+        assert!(
+            lens.windows(2).all(|w| w[0] > w[1] * TIER_RATIO),
+            "{:?}",
+            lens
+        );
+        assert_eq!(100_000, lens.iter().sum::<usize>());
+        assert_eq!(Some(record(1, 1)), store.get(54_321).unwrap());
+    }
+
+    #[test]
+    fn test_large_runs_use_fences() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = store(dir.path(), 3000);
+        for tx in (0..6001).map(|t| t * 3) {
+            store.insert(tx, record(1, tx as u64)).unwrap();
+        }
+        for tx in [0, 3, 1536, 1537, 4500, 9000, 18000] {
+            let expected = (tx % 3 == 0).then(|| record(1, tx as u64));
+            assert_eq!(expected, store.get(tx).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stores_share_spill_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut first = store(dir.path(), 1);
+        let mut second = store(dir.path(), 1);
+        for tx in 0..4 {
+            first.insert(tx, record(1, 1)).unwrap();
+            second.insert(tx, record(2, 2)).unwrap();
+        }
+        assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+        for tx in 0..4 {
+            assert_eq!(Some(record(1, 1)), first.get(tx).unwrap());
+            assert_eq!(Some(record(2, 2)), second.get(tx).unwrap());
+        }
+        drop(first);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn test_files_removed_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill_dir = dir.path().join("spill");
+        {
+            let mut store = store(&spill_dir, 1);
+            store.insert(1, record(1, 1)).unwrap();
+            store.insert(2, record(1, 1)).unwrap();
+            assert!(fs::read_dir(&spill_dir).unwrap().count() > 0);
+        }
+        // The directory was given, only the files of the store are removed.
+        assert_eq!(0, fs::read_dir(&spill_dir).unwrap().count());
+    }
+
+    #[test]
+    fn test_temporary_dir_removed_on_drop() {
+        let mut first = SpillTxStore::temporary(0).unwrap();
+        let second = SpillTxStore::temporary(0).unwrap();
+        let parent = first.temporary.clone().unwrap();
+        first.insert(1, record(1, 1)).unwrap();
+        drop(first);
+        assert!(parent.exists());
+        drop(second);
+        assert!(!parent.exists());
+    }
+}
@@ -0,0 +1,268 @@
+use super::TxStore;
+use super::act_sqlite::{BUSY_TIMEOUT, SharedConnection, lock, migrate};
+use crate::types::{TxKind, TxRecord, TxState};
+use anyhow::{Result, anyhow};
+use rusqlite::types::Type;
+use rusqlite::{Connection, OptionalExtension, Params, Row, params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Transaction store persisted in the same SQLite database as a
+/// [`super::SqliteActStore`], so that transactions of earlier runs can be
+/// disputed and their ids are known.
+///
+/// Records are read from the database on every lookup, nothing is cached.
+/// A store opened with [`super::SqliteActStore::tx_store`] writes within the
+/// SQLite transaction of the account store.
+pub struct SqliteTxStore {
+    conn: SharedConnection,
+}
+
+impl SqliteTxStore {
+    /// Opens or creates the database at `path` and applies pending migrations.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Creates a store backed by a private in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut conn)?;
+        Ok(Self::with_shared(Arc::new(Mutex::new(conn))))
+    }
+
+    pub(super) fn with_shared(conn: SharedConnection) -> Self {
+        SqliteTxStore { conn }
+    }
+
+    /// Records matching the `WHERE` clause, ordered by transaction id.
+    fn select<P: Params>(&self, condition: &str, params: P) -> Result<Vec<(u32, TxRecord)>> {
+        let conn = lock(&self.conn)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT tx, client, amount, state, kind FROM transactions WHERE {} ORDER BY tx",
+            condition
+        ))?;
+        let records = stmt
+            .query_map(params, |row| Ok((row.get(0)?, record(row, 1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+}
+
+/// Reads the record starting at column `first`.
+fn record(row: &Row, first: usize) -> rusqlite::Result<TxRecord> {
+    let text = |idx: usize| -> rusqlite::Result<String> { row.get(idx) };
+    let invalid = |idx: usize, value: String| {
+        rusqlite::Error::FromSqlConversionFailure(
+            idx,
+            Type::Text,
+            anyhow!("unknown value {}", value).into(),
+        )
+    };
+    let state = match text(first + 2)?.as_str() {
+        "settled" => TxState::Settled,
+        "disputed" => TxState::Disputed,
+        "charged_back" => TxState::ChargedBack,
+        other => return Err(invalid(first + 2, other.to_string())),
+    };
+    let kind = match text(first + 3)?.as_str() {
+        "deposit" => TxKind::Deposit,
+        "withdrawal" => TxKind::Withdrawal,
+        other => return Err(invalid(first + 3, other.to_string())),
+    };
+    Ok(TxRecord {
+        client: row.get(first)?,
+        amount: row.get(first + 1)?,
+        state,
+        kind,
+    })
+}
+
+fn state_name(state: TxState) -> &'static str {
+    match state {
+        TxState::Settled => "settled",
+        TxState::Disputed => "disputed",
+        TxState::ChargedBack => "charged_back",
+    }
+}
+
+fn kind_name(kind: TxKind) -> &'static str {
+    match kind {
+        TxKind::Deposit => "deposit",
+        TxKind::Withdrawal => "withdrawal",
+    }
+}
+
+impl TxStore for SqliteTxStore {
+    fn insert(&mut self, tx: u32, record: TxRecord) -> Result<()> {
+        lock(&self.conn)?.execute(
+            "INSERT OR REPLACE INTO transactions (tx, client, amount, state, kind)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                tx,
+                record.client,
+                record.amount,
+                state_name(record.state),
+                kind_name(record.kind)
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get(&self, tx: u32) -> Result<Option<TxRecord>> {
+        Ok(lock(&self.conn)?
+            .query_row(
+                "SELECT client, amount, state, kind FROM transactions WHERE tx = ?1",
+                params![tx],
+                |row| record(row, 0),
+            )
+            .optional()?)
+    }
+
+    fn set_state(&mut self, tx: u32, state: TxState) -> Result<()> {
+        match lock(&self.conn)?.execute(
+            "UPDATE transactions SET state = ?2 WHERE tx = ?1",
+            params![tx, state_name(state)],
+        )? {
+            0 => Err(anyhow!("Transaction {} not found", tx)),
+            _ => Ok(()),
+        }
+    }
+
+    fn client_txs(&self, client: u16) -> Result<Vec<(u32, TxRecord)>> {
+        self.select("client = ?1", params![client])
+    }
+
+    fn disputed(&self) -> Result<Vec<(u32, TxRecord)>> {
+        self.select("state = ?1", params![state_name(TxState::Disputed)])
+    }
+
+    fn records(&self) -> Result<Vec<(u32, TxRecord)>> {
+        self.select("1", [])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::process;
+    use crate::stores::{ActStore, SqliteActStore};
+    use crate::types::{Transaction, TransactionType};
+
+    fn record(client: u16, amount: u64) -> TxRecord {
+        TxRecord {
+            client,
+            amount,
+            state: TxState::Settled,
+            kind: TxKind::Deposit,
+        }
+    }
+
+    #[test]
+    fn test_insert_get_set_state() {
+        let mut store = SqliteTxStore::open_in_memory().unwrap();
+        store.insert(1, record(1, 100)).unwrap();
+        store
+            .insert(
+                2,
+                TxRecord {
+                    kind: TxKind::Withdrawal,
+                    ..record(2, 50)
+                },
+            )
+            .unwrap();
+        store.insert(3, record(1, 30)).unwrap();
+        assert_eq!(Some(record(1, 100)), store.get(1).unwrap());
+        assert_eq!(TxKind::Withdrawal, store.get(2).unwrap().unwrap().kind);
+        assert_eq!(None, store.get(4).unwrap());
+        store.set_state(3, TxState::Disputed).unwrap();
+        store
+            .set_state(4, TxState::Disputed)
+            .expect_err("Unknown transactions cannot be disputed");
+        let ids = |txs: Vec<(u32, TxRecord)>| txs.iter().map(|(tx, _)| *tx).collect::<Vec<_>>();
+        assert_eq!(vec![1, 3], ids(store.client_txs(1).unwrap()));
+        assert_eq!(vec![3], ids(store.disputed().unwrap()));
+        assert_eq!(vec![1, 2, 3], ids(store.records().unwrap()));
+    }
+
+    #[test]
+    fn test_shares_the_account_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("act.db");
+        {
+            let mut act_store = SqliteActStore::open(&path).unwrap();
+            let mut tx_store = act_store.tx_store();
+            act_store.deposit(1, 100).unwrap();
+            tx_store.insert(1, record(1, 100)).unwrap();
+            tx_store.set_state(1, TxState::ChargedBack).unwrap();
+        }
+        let tx_store = SqliteTxStore::open(&path).unwrap();
+        assert_eq!(
+            TxState::ChargedBack,
+            tx_store.get(1).unwrap().unwrap().state
+        );
+        assert_eq!(
+            100,
+            SqliteActStore::open(&path)
+                .unwrap()
+                .get_account(1)
+                .unwrap()
+                .total()
+        );
+    }
+
+    #[test]
+    fn test_second_run_rejects_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("act.db");
+        let txs = [
+            (TransactionType::Deposit, 1, 10000),
+            (TransactionType::Withdrawal, 2, 4000),
+        ];
+        for run in 0..2 {
+            let mut act_store = SqliteActStore::open(&path).unwrap();
+            let mut tx_store = act_store.tx_store();
+            for (tx_type, tx, amount) in txs {
+                let t = Transaction {
+                    tx_type,
+                    client: 1,
+                    tx,
+                    amount,
+                };
+                let res = process(t, &mut act_store, &mut tx_store);
+                assert_eq!(run == 0, res.is_ok(), "run {}, tx {}: {:?}", run, tx, res);
+            }
+            assert_eq!(6000, act_store.get_account(1).unwrap().total());
+        }
+    }
+
+    #[test]
+    fn test_failed_record_rolls_back_the_account() {
+        let mut act_store = SqliteActStore::open_in_memory().unwrap();
+        let mut tx_store = act_store.tx_store();
+        lock(&tx_store.conn)
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject BEFORE INSERT ON transactions
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+            )
+            .unwrap();
+        let t = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: 10000,
+        };
+        process(t, &mut act_store, &mut tx_store).expect_err("Insert should be rejected");
+        assert!(act_store.get_account(1).is_none());
+        let accounts: i64 = lock(&tx_store.conn)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM accounts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(0, accounts);
+    }
+}
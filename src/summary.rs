@@ -0,0 +1,229 @@
+use crate::audit::{AuditRecord, AuditSink};
+use crate::stores::{AccountFilter, AccountOrder, ActStore};
+use crate::types::amount::format_amount;
+use crate::types::{Account, TransactionType};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Number of negative balances listed in a report.
+const NEGATIVE_BALANCES: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub applied: u64,
+    pub rejected: u64,
+}
+
+/// Counts the outcome of every row and the funds moved.
+#[derive(Debug, Default)]
+pub struct Summary {
+    rows: u64,
+    invalid: u64,
+    types: BTreeMap<TransactionType, Counts>,
+    deposited: i128,
+    withdrawn: i128,
+    charged_back: i128,
+}
+
+/// Aggregated figures of a run, amounts are exact decimal strings.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Report {
+    pub rows: u64,
+    pub parsed: u64,
+    pub invalid: u64,
+    /// Parsed rows applied
+    pub applied: u64,
+    /// Parsed rows rejected
+    pub rejected: u64,
+    pub types: BTreeMap<TransactionType, Counts>,
+    pub clients: usize,
+    pub locked: usize,
+    pub deposited: String,
+    pub withdrawn: String,
+    pub held: String,
+    pub charged_back: String,
+    /// Accounts with a negative total, lowest first
+    pub largest_negative: Vec<Account>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Report of the rows seen so far and the accounts of the store.
+    pub fn report(&self, store: &dyn ActStore) -> Report {
+        let applied: u64 = self.types.values().map(|c| c.applied).sum();
+        let rejected: u64 = self.types.values().map(|c| c.rejected).sum();
+        let negative = AccountFilter {
+            negative: true,
+            ..Default::default()
+        };
+        let mut largest_negative = store.select(&negative, AccountOrder::Balance);
+        largest_negative.reverse();
+        largest_negative.truncate(NEGATIVE_BALANCES);
+        Report {
+            rows: self.rows,
+            parsed: self.rows - self.invalid,
+            invalid: self.invalid,
+            applied,
+            rejected,
+            types: self.types.clone(),
+            clients: store.count(),
+            locked: store.accounts().filter(|act| act.is_locked()).count(),
+            deposited: format_amount(self.deposited),
+            withdrawn: format_amount(self.withdrawn),
+            held: format_amount(
+                store
+                    .accounts()
+                    .map(|act| i128::from(act.held()))
+                    .sum::<i128>(),
+            ),
+            charged_back: format_amount(self.charged_back),
+            largest_negative: largest_negative.into_iter().cloned().collect(),
+        }
+    }
+}
+
+impl Report {
+    pub fn write_text<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(
+            w,
+            "rows: {} read, {} parsed, {} invalid",
+            self.rows, self.parsed, self.invalid
+        )?;
+        writeln!(
+            w,
+            "transactions: {} applied, {} rejected",
+            self.applied, self.rejected
+        )?;
+        for (tx_type, counts) in self.types.iter() {
+            writeln!(
+                w,
+                "  {:<10}  {} applied, {} rejected",
+                format!("{:?}", tx_type).to_lowercase(),
+                counts.applied,
+                counts.rejected
+            )?;
+        }
+        writeln!(w, "clients: {}, {} locked", self.clients, self.locked)?;
+        writeln!(w, "deposited: {}", self.deposited)?;
+        writeln!(w, "withdrawn: {}", self.withdrawn)?;
+        writeln!(w, "held: {}", self.held)?;
+        writeln!(w, "charged back: {}", self.charged_back)?;
+        if !self.largest_negative.is_empty() {
+            writeln!(w, "largest negative balances:")?;
+            for act in self.largest_negative.iter() {
+                writeln!(w, "  client {}: {}", act.id(), format_amount(act.total()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AuditSink for Summary {
+    fn record(&mut self, rec: &AuditRecord) -> Result<()> {
+        self.rows += 1;
+        let Some(t) = &rec.tx else {
+            self.invalid += 1;
+            return Ok(());
+        };
+        let counts = self.types.entry(t.tx_type).or_default();
+        if !rec.applied() {
+            counts.rejected += 1;
+            return Ok(());
+        }
+        counts.applied += 1;
+        match t.tx_type {
+            TransactionType::Deposit => self.deposited += i128::from(t.amount),
+            TransactionType::Withdrawal => self.withdrawn += i128::from(t.amount),
+            TransactionType::Chargeback => {
+                let total =
+                    |act: &Option<Account>| i128::from(act.as_ref().map_or(0, Account::total));
+                self.charged_back += total(&rec.before) - total(&rec.after);
+            }
+            TransactionType::Dispute | TransactionType::Resolve => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::Fixture;
+    use crate::stores::MemActStore;
+
+    fn summary() -> (Summary, MemActStore) {
+        let mut fixture = Fixture::new();
+        let mut summary = Summary::new();
+        for rec in fixture.apply_all(&[
+            (TransactionType::Deposit, 1, 1, 10000),
+            (TransactionType::Deposit, 2, 2, 20000),
+            (TransactionType::Withdrawal, 1, 3, 4000),
+            (TransactionType::Withdrawal, 2, 4, 30000),
+            (TransactionType::Dispute, 1, 1, 0),
+            (TransactionType::Chargeback, 1, 1, 0),
+            (TransactionType::Dispute, 2, 2, 0),
+            (TransactionType::Resolve, 3, 9, 0),
+        ]) {
+            summary.record(&rec).unwrap();
+        }
+        summary
+            .record(&AuditRecord::invalid(9, String::from("bad row")))
+            .unwrap();
+        (summary, fixture.act_store)
+    }
+
+    #[test]
+    fn test_report() {
+        let (summary, act_store) = summary();
+        let report = summary.report(&act_store);
+        assert_eq!(9, report.rows);
+        assert_eq!(8, report.parsed);
+        assert_eq!(6, report.applied);
+        assert_eq!(2, report.rejected);
+        assert_eq!(
+            Counts {
+                applied: 1,
+                rejected: 1
+            },
+            report.types[&TransactionType::Withdrawal]
+        );
+        assert_eq!(
+            Counts {
+                applied: 0,
+                rejected: 1
+            },
+            report.types[&TransactionType::Resolve]
+        );
+        assert_eq!(1, report.locked);
+        assert_eq!("3.0000", report.deposited);
+        assert_eq!("0.4000", report.withdrawn);
+        assert_eq!("2.0000", report.held);
+        assert_eq!("1.0000", report.charged_back);
+        assert_eq!(1, report.largest_negative.len());
+        assert_eq!(-4000, report.largest_negative[0].total());
+    }
+
+    #[test]
+    fn test_json() {
+        let (summary, act_store) = summary();
+        let json = serde_json::to_value(summary.report(&act_store)).unwrap();
+        assert_eq!(1, json["types"]["chargeback"]["applied"]);
+        assert_eq!("-0.4000", json["largest_negative"][0]["total"]);
+    }
+
+    #[test]
+    fn test_text() {
+        let (summary, act_store) = summary();
+        let mut out = Vec::new();
+        summary.report(&act_store).write_text(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("rows: 9 read, 8 parsed, 1 invalid\n"));
+        assert!(out.contains("  withdrawal  1 applied, 1 rejected\n"));
+        assert!(out.ends_with("largest negative balances:\n  client 1: -0.4000\n"));
+    }
+}
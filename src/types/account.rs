@@ -1,8 +1,8 @@
+use super::amount::format_amount;
 use anyhow::{Result, anyhow, bail};
 use serde::{Serialize, Serializer};
-const PRECISION: u32 = 4;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Account {
     id: u16,
     /// Total balance in the account.
@@ -31,6 +31,16 @@ impl Account {
         }
     }
 
+    /// Rebuilds an account from its persisted fields.
+    pub(crate) fn from_parts(client_id: u16, total: i64, held: u64, locked: bool) -> Account {
+        Account {
+            id: client_id,
+            total,
+            held,
+            locked,
+        }
+    }
+
     pub fn id(&self) -> u16 {
         self.id
     }
@@ -42,6 +52,10 @@ impl Account {
             self.held
         ))
     }
+    /// Available balance computed without overflow, for reports.
+    pub fn available_exact(&self) -> i128 {
+        i128::from(self.total) - i128::from(self.held)
+    }
     pub fn held(&self) -> u64 {
         self.held
     }
@@ -94,10 +108,15 @@ impl Account {
     }
     fn internal_withdraw(&mut self, amnt: u64, allow_negative: bool) -> Result<i64> {
         let available = self.available()?;
-        match available.checked_sub_unsigned(amnt) {
-            Some(total) if total >= 0 || allow_negative => self.total = total,
-            Some(total) => bail!("Withdrawal would result in negative balance: {}", total),
-            None => bail!(
+        match (
+            available.checked_sub_unsigned(amnt),
+            self.total.checked_sub_unsigned(amnt),
+        ) {
+            (Some(left), Some(total)) if left >= 0 || allow_negative => self.total = total,
+            (Some(left), Some(_)) => {
+                bail!("Withdrawal would result in negative balance: {}", left)
+            }
+            _ => bail!(
                 "Withdrawal overflow. available={} withdraw={}",
                 available,
                 amnt
@@ -128,13 +147,37 @@ impl Account {
     }
 }
 
+/// Serialized form of an account, amounts are exact decimal strings.
 #[derive(Debug, Serialize, PartialEq)]
 pub struct AccountSer {
-    id: u16,
-    total: String,
-    held: String,
+    client: u16,
     available: String,
+    held: String,
+    total: String,
     locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disputes: Option<usize>,
+}
+
+impl AccountSer {
+    /// Adds the number of open disputes of the account.
+    pub fn with_disputes(mut self, disputes: usize) -> Self {
+        self.disputes = Some(disputes);
+        self
+    }
+}
+
+impl From<&Account> for AccountSer {
+    fn from(act: &Account) -> Self {
+        AccountSer {
+            client: act.id,
+            available: format_amount(act.available_exact()),
+            held: format_amount(act.held),
+            total: format_amount(act.total),
+            locked: act.locked,
+            disputes: None,
+        }
+    }
 }
 
 impl serde::Serialize for Account {
@@ -142,22 +185,7 @@ impl serde::Serialize for Account {
     where
         S: Serializer,
     {
-        let dec = 10i64.pow(PRECISION);
-        let udec = 10u64.pow(PRECISION);
-
-        let total = format!("{}.{}", self.total / dec, self.total.abs() % dec);
-        let held = format!("{}.{}", self.held / udec, self.held % udec);
-        let available = self.available().map_or(String::from("0"), |a| {
-            format!("{}.{}", a / dec, a.abs() % dec)
-        });
-        let ser = AccountSer {
-            id: self.id,
-            total,
-            held,
-            available,
-            locked: self.locked,
-        };
-        ser.serialize(s)
+        AccountSer::from(self).serialize(s)
     }
 }
 
@@ -234,6 +262,22 @@ mod tests {
             .unhold(200)
             .expect_err("Unhold should fail due to insufficient held amount");
     }
+    #[test]
+    fn test_withdraw_keeps_held_funds() {
+        let mut account = Account::with_balance(900, 300);
+        account.hold(100).expect("Hold failed");
+        let balance = account.withdraw(150);
+        assert!(matches!(balance, Ok(50)));
+        assert_eq!(account.total(), 150);
+        assert_eq!(account.held(), 100);
+        account
+            .withdraw(100)
+            .expect_err("Withdraw should not use held funds");
+        let balance = account.withdraw_allow_negative(100);
+        assert!(matches!(balance, Ok(-50)));
+        assert_eq!(account.total(), 50);
+    }
+
     #[test]
     fn test_withdraw_negative_balance() {
         let mut account = Account::new(800);
@@ -278,4 +322,13 @@ mod tests {
             i64::MAX - 1
         );
     }
+
+    #[test]
+    fn test_serialize_overflowing_available() {
+        let account = Account::from_parts(1, i64::MIN, 10000, false);
+        account.available().expect_err("Available overflows i64");
+        let ser = AccountSer::from(&account);
+        assert_eq!("-922337203685478.5808", ser.available);
+        assert_eq!("-922337203685477.5808", ser.total);
+    }
 }
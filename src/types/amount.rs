@@ -0,0 +1,112 @@
+use anyhow::{Result, anyhow};
+
+/// Number of decimal places of amounts, an amount of 1 is 0.0001.
+pub const PRECISION: u32 = 4;
+
+/// Parses a decimal amount such as `12.3456` into the number of smallest units.
+pub fn parse_amount(s: &str) -> Result<u64> {
+    Ok(units(s)?.parse::<u64>()?)
+}
+
+/// Digits of the number of smallest units of an unsigned decimal amount.
+fn units(s: &str) -> Result<String> {
+    let mut split = s.split('.');
+    let units = split.next().map_or("0", |v| match v {
+        "" => "0",
+        _ => v,
+    });
+
+    let dec = match split.next() {
+        Some(v) if v.len() > PRECISION as usize => {
+            Err(anyhow!("decimal precision not supported: {}", v))
+        }
+        Some(v) => Ok(v),
+        None => Ok("0"),
+    }
+    .map(|v| format!("{:0<4.4}", v))?;
+    Ok(units.to_string() + &dec)
+}
+
+/// Same as [`parse_amount`], accepting a leading `-`.
+pub fn parse_signed_amount(s: &str) -> Result<i64> {
+    match s.strip_prefix('-') {
+        Some(abs) => {
+            let abs = parse_amount(abs)?;
+            0i64.checked_sub_unsigned(abs)
+                .ok_or(anyhow!("amount out of range: {}", s))
+        }
+        None => i64::try_from(parse_amount(s)?).map_err(|_| anyhow!("amount out of range: {}", s)),
+    }
+}
+
+/// Same as [`parse_signed_amount`] over the range of
+/// [`crate::types::Account::available_exact`], which exceeds `i64`.
+pub fn parse_exact_amount(s: &str) -> Result<i128> {
+    let (sign, abs) = s.strip_prefix('-').map_or((1, s), |abs| (-1, abs));
+    let abs = i128::try_from(units(abs)?.parse::<u128>()?)
+        .map_err(|_| anyhow!("amount out of range: {}", s))?;
+    Ok(sign * abs)
+}
+
+/// Formats a number of smallest units as an exact decimal, e.g. `-1.0500`.
+pub fn format_amount<T: Into<i128>>(amnt: T) -> String {
+    let amnt: i128 = amnt.into();
+    let dec = 10i128.pow(PRECISION);
+    let sign = if amnt < 0 { "-" } else { "" };
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        (amnt / dec).abs(),
+        (amnt % dec).abs(),
+        width = PRECISION as usize
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(12345, parse_amount("1.2345").unwrap());
+        assert_eq!(10000, parse_amount("1").unwrap());
+        assert_eq!(5000, parse_amount(".5").unwrap());
+        assert_eq!(0, parse_amount("").unwrap());
+        parse_amount("1.23456").expect_err("Too many decimals");
+        parse_amount("-1").expect_err("Negative amounts are not allowed");
+    }
+
+    #[test]
+    fn test_parse_signed_amount() {
+        assert_eq!(-15000, parse_signed_amount("-1.5").unwrap());
+        assert_eq!(15000, parse_signed_amount("1.5").unwrap());
+        assert_eq!(
+            i64::MIN,
+            parse_signed_amount("-922337203685477.5808").unwrap()
+        );
+        parse_signed_amount("922337203685477.5808").expect_err("Above i64::MAX");
+        parse_signed_amount("--1").expect_err("Double sign");
+    }
+
+    #[test]
+    fn test_parse_exact_amount() {
+        let below = i128::from(i64::MIN) - i128::from(u64::MAX);
+        assert_eq!(below, parse_exact_amount(&format_amount(below)).unwrap());
+        assert_eq!(-15000, parse_exact_amount("-1.5").unwrap());
+        parse_exact_amount("-1.23456").expect_err("Too many decimals");
+        parse_exact_amount("--1").expect_err("Double sign");
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!("0.0000", format_amount(0));
+        assert_eq!("1.0500", format_amount(10500));
+        assert_eq!("-0.5000", format_amount(-5000));
+        assert_eq!("-922337203685477.5808", format_amount(i64::MIN));
+        assert_eq!("1844674407370955.1615", format_amount(u64::MAX));
+        assert_eq!(
+            u64::MAX,
+            parse_amount(&format_amount(u64::MAX)).expect("Round trip")
+        );
+    }
+}
@@ -1,5 +1,8 @@
+pub mod amount;
 pub mod transaction;
 pub use transaction::Transaction;
 pub use transaction::TransactionType;
 pub mod account;
 pub use account::Account;
+pub mod tx_record;
+pub use tx_record::{TxKind, TxRecord, TxState};
@@ -1,9 +1,8 @@
-use anyhow::{Result, anyhow};
+use super::amount::parse_amount;
 use serde::de;
-use serde::{Deserialize, Deserializer};
-const PRECISION: u32 = 4;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Eq, PartialEq, Debug, Deserialize, Clone)]
+#[derive(Eq, PartialEq, Debug, Deserialize, Serialize, Clone, Copy, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -20,7 +19,7 @@ pub struct Transaction {
     pub client: u16,
     pub tx: u32,
     /// Amount of the smallest unit, e.g. 0.0001 as per the specification
-    #[serde(deserialize_with = "de_amount")]
+    #[serde(default, deserialize_with = "de_amount")]
     pub amount: u64,
 }
 
@@ -29,22 +28,5 @@ where
     D: Deserializer<'de>,
 {
     let deserialized = String::deserialize(deserializer)?;
-    let mut split = deserialized.split('.');
-    let units = split.next().map_or("0", |v| match v {
-        "" => "0",
-        _ => v,
-    });
-
-    let dec = match split.next() {
-        Some(v) if v.len() > PRECISION as usize => {
-            Err(anyhow!("decimal precision not supported: {}", v))
-        }
-        Some(v) => Ok(v),
-        None => Ok("0"),
-    }
-    .map(|v| format!("{:0<4.4}", v))
-    .map_err(de::Error::custom)?;
-    (units.to_string() + &dec)
-        .parse::<u64>()
-        .map_err(de::Error::custom)
+    parse_amount(&deserialized).map_err(de::Error::custom)
 }
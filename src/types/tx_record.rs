@@ -0,0 +1,46 @@
+use super::{Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+
+/// What transaction stores keep about a processed deposit, or withdrawal
+/// when they can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxRecord {
+    pub client: u16,
+    /// Amount of the smallest unit, see [`Transaction::amount`]
+    pub amount: u64,
+    pub state: TxState,
+    pub kind: TxKind,
+}
+
+/// Transaction a record was made from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    #[default]
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxState {
+    #[default]
+    Settled,
+    Disputed,
+    /// Final, a charged back transaction cannot be disputed again.
+    ChargedBack,
+}
+
+impl From<&Transaction> for TxRecord {
+    fn from(t: &Transaction) -> Self {
+        TxRecord {
+            client: t.client,
+            amount: t.amount,
+            state: TxState::Settled,
+            kind: match t.tx_type {
+                TransactionType::Withdrawal => TxKind::Withdrawal,
+                _ => TxKind::Deposit,
+            },
+        }
+    }
+}
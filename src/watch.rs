@@ -0,0 +1,333 @@
+use crate::engine::Engine;
+use crate::output::OutputFormat;
+use crate::parse::{RecordParser, parse_rows};
+use crate::process::Policy;
+use crate::snapshot::{FileMark, Snapshot, write_atomic};
+use anyhow::{Result, anyhow, bail};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio_stream::StreamExt;
+
+/// Columns an input file must have.
+const REQUIRED: [&str; 3] = ["type", "client", "tx"];
+
+pub struct WatchOpts {
+    /// Inbox, holds the `processed` and `failed` directories
+    pub dir: PathBuf,
+    pub state: PathBuf,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    /// Time a file must go unmodified before it is considered complete
+    pub settle: Duration,
+    pub policy: Policy,
+}
+
+impl WatchOpts {
+    /// State and output in the inbox, files complete after 5 seconds.
+    pub fn new(dir: PathBuf) -> Self {
+        WatchOpts {
+            state: dir.join("state.json"),
+            output: dir.join("accounts.csv"),
+            format: OutputFormat::Csv,
+            settle: Duration::from_secs(5),
+            policy: Policy::default(),
+            dir,
+        }
+    }
+}
+
+/// Processes the files dropped in a directory into an engine saved in a
+/// snapshot after each file.
+///
+/// A file is processed once it has not been modified for the settle time,
+/// files being taken by name. Once its rows are applied the snapshot is
+/// saved, then the account output, then the file is moved to `processed`.
+/// Files that cannot be read or lack the required columns are moved to
+/// `failed` with a `.error` file next to them and leave the state untouched.
+/// The snapshot names the last file applied with the length and hash of its
+/// contents, if it is still in the inbox after a restart it is moved without
+/// being applied again.
+pub struct Watcher {
+    opts: WatchOpts,
+    engine: Engine<'static>,
+}
+
+impl Watcher {
+    /// Resumes from the snapshot, if any.
+    pub fn open(opts: WatchOpts) -> Result<Self> {
+        fs::create_dir_all(opts.dir.join("processed"))?;
+        fs::create_dir_all(opts.dir.join("failed"))?;
+        let snapshot = Snapshot::read(&opts.state)?.unwrap_or_default();
+        let (act_store, tx_store) = snapshot.stores()?;
+        let watcher = Watcher {
+            engine: Engine::builder()
+                .act_store(Box::new(act_store))
+                .tx_store(Box::new(tx_store))
+                .policy(opts.policy)
+                .build(),
+            opts,
+        };
+        if let Some(mark) = snapshot.mark {
+            let path = watcher.opts.dir.join(&mark.name);
+            if fs::read(&path).is_ok_and(|data| mark.matches(&data)) {
+                info!("{} was applied before stopping", mark.name);
+                watcher.move_to(&path, "processed")?;
+            }
+        }
+        watcher.write_output()?;
+        Ok(watcher)
+    }
+
+    pub fn engine(&self) -> &Engine<'static> {
+        &self.engine
+    }
+
+    /// Completed files waiting in the inbox, ordered by name.
+    pub fn pending(&self) -> Result<Vec<PathBuf>> {
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.opts.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let meta = entry.metadata()?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            let ours = [&self.opts.state, &self.opts.output]
+                .iter()
+                .any(|p| same_file(p, &path));
+            if !meta.is_file() || hidden || ours || path.extension().is_some_and(|e| e == "tmp") {
+                continue;
+            }
+            let age = now.duration_since(meta.modified()?).unwrap_or_default();
+            if age >= self.opts.settle {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Processes the pending files, returns how many were applied.
+    pub async fn poll(&mut self) -> Result<usize> {
+        let mut applied = 0;
+        for path in self.pending()? {
+            if self.process(&path).await? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Polls the inbox every `interval` until an error.
+    pub async fn run(mut self, interval: Duration) -> Result<()> {
+        loop {
+            self.poll().await?;
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Applies a file, false if it failed and was moved to `failed`.
+    async fn process(&mut self, path: &Path) -> Result<bool> {
+        let name = file_name(path)?;
+        let data = match read_input(path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("{} failed: {}", name, e);
+                let failed = self.move_to(path, "failed")?;
+                let mut error = failed.into_os_string();
+                error.push(".error");
+                fs::write(error, format!("{}\n", e))?;
+                return Ok(false);
+            }
+        };
+
+        let rows = parse_rows(data.as_bytes());
+        tokio::pin!(rows);
+        let (mut applied, mut rejected) = (0, 0);
+        while let Some(row) = rows.next().await {
+            match self.engine.apply_row(row)?.applied() {
+                true => applied += 1,
+                false => rejected += 1,
+            }
+        }
+        let mark = FileMark::new(name.clone(), data.as_bytes());
+        Snapshot::of(&self.engine)?
+            .with_mark(Some(mark))
+            .write(&self.opts.state)?;
+        self.write_output()?;
+        self.move_to(path, "processed")?;
+        info!("{}: {} rows applied, {} rejected", name, applied, rejected);
+        Ok(true)
+    }
+
+    fn write_output(&self) -> Result<()> {
+        write_atomic(&self.opts.output, |w| {
+            self.engine.export(w, self.opts.format)?;
+            Ok(())
+        })
+    }
+
+    /// Moves a file of the inbox to `sub`, numbering it if the name is
+    /// taken.
+    fn move_to(&self, path: &Path, sub: &str) -> Result<PathBuf> {
+        let name = file_name(path)?;
+        let dir = self.opts.dir.join(sub);
+        let mut to = dir.join(&name);
+        let mut n = 1;
+        while to.exists() {
+            to = dir.join(format!("{}.{}", name, n));
+            n += 1;
+        }
+        fs::rename(path, &to)?;
+        Ok(to)
+    }
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or(anyhow!("not a file: {}", path.display()))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Contents of an input file, checked to be text with the required columns.
+fn read_input(path: &Path) -> Result<String> {
+    let data = String::from_utf8(fs::read(path)?).map_err(|_| anyhow!("not UTF-8 text"))?;
+    let header = data.lines().next().unwrap_or_default();
+    let parser = RecordParser::from_header_line(header)?;
+    let missing: Vec<&str> = REQUIRED
+        .into_iter()
+        .filter(|col| !parser.has_column(col))
+        .collect();
+    if !missing.is_empty() {
+        bail!("missing columns: {}", missing.join(", "));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(dir: &Path) -> WatchOpts {
+        WatchOpts {
+            settle: Duration::ZERO,
+            ..WatchOpts::new(dir.to_path_buf())
+        }
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_processes_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path();
+        fs::write(inbox.join("2.csv"), "type,client,tx,amount\ndispute,1,1,\n").unwrap();
+        fs::write(
+            inbox.join("1.csv"),
+            "type,client,tx,amount\ndeposit,1,1,2.5\n",
+        )
+        .unwrap();
+        fs::write(inbox.join("3.csv"), "client,amount\n1,1.0\n").unwrap();
+        fs::write(inbox.join(".4.csv.part"), "type,client").unwrap();
+
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        assert_eq!(2, watcher.poll().await.unwrap());
+        assert_eq!(25000, watcher.engine().account(1).unwrap().held());
+        assert_eq!(vec!["1.csv", "2.csv"], names(&inbox.join("processed")));
+        assert_eq!(vec!["3.csv", "3.csv.error"], names(&inbox.join("failed")));
+        assert_eq!(
+            "missing columns: type, tx\n",
+            fs::read_to_string(inbox.join("failed/3.csv.error")).unwrap()
+        );
+        assert_eq!(
+            "client,available,held,total,locked\n1,0.0000,2.5000,2.5000,false\n",
+            fs::read_to_string(inbox.join("accounts.csv")).unwrap()
+        );
+        assert_eq!(0, watcher.poll().await.unwrap());
+
+        // A restart resumes from the snapshot.
+        drop(watcher);
+        fs::write(inbox.join("5.csv"), "type,client,tx,amount\nresolve,1,1,\n").unwrap();
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        assert_eq!(1, watcher.poll().await.unwrap());
+        assert_eq!(
+            25000,
+            watcher.engine().account(1).unwrap().available().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recovers_file_applied_before_stopping() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path();
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+        fs::write(inbox.join("1.csv"), data).unwrap();
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        watcher.poll().await.unwrap();
+        drop(watcher);
+        // As if stopped after the snapshot was saved but before the move.
+        fs::rename(inbox.join("processed/1.csv"), inbox.join("1.csv")).unwrap();
+
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        assert_eq!(0, watcher.poll().await.unwrap());
+        assert_eq!(10000, watcher.engine().account(1).unwrap().total());
+        assert_eq!(vec!["1.csv"], names(&inbox.join("processed")));
+    }
+
+    #[tokio::test]
+    async fn test_applies_new_file_with_the_name_of_the_last_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path();
+        fs::write(
+            inbox.join("1.csv"),
+            "type,client,tx,amount
+deposit,1,1,1.0
+",
+        )
+        .unwrap();
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        watcher.poll().await.unwrap();
+        drop(watcher);
+        // Same name and length, different contents.
+        fs::write(
+            inbox.join("1.csv"),
+            "type,client,tx,amount
+deposit,1,2,2.0
+",
+        )
+        .unwrap();
+
+        let mut watcher = Watcher::open(opts(inbox)).unwrap();
+        assert_eq!(1, watcher.poll().await.unwrap());
+        assert_eq!(30000, watcher.engine().account(1).unwrap().total());
+        assert_eq!(vec!["1.csv", "1.csv.1"], names(&inbox.join("processed")));
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_files_to_settle() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path();
+        fs::write(inbox.join("1.csv"), "type,client,tx,amount\n").unwrap();
+        let watcher = Watcher::open(WatchOpts {
+            settle: Duration::from_secs(60),
+            ..WatchOpts::new(inbox.to_path_buf())
+        })
+        .unwrap();
+        assert!(watcher.pending().unwrap().is_empty());
+    }
+}